@@ -0,0 +1,88 @@
+//! Forking cost of `Computer`: a fork followed by a single write for growing
+//! memory sizes, and the Day 15 map exploration, which clones the droid's
+//! machine for every step of its breadth-first search.
+
+use std::collections::{HashSet, VecDeque};
+
+use advent_of_code_2019::intcode::memory::{DenseMemory, Memory};
+use advent_of_code_2019::intcode::{Computer, RunResult};
+use criterion::measurement::WallTime;
+use criterion::{
+    black_box, criterion_group, criterion_main, BenchmarkGroup, BenchmarkId, Criterion,
+};
+
+const DAY15: &str = include_str!("../inputs/day15.txt");
+
+fn droid<M: Memory<Word = isize>>() -> Computer<M> {
+    let mut computer: Computer<M> = DAY15.trim().parse().unwrap();
+    assert_eq!(computer.run(None).unwrap(), RunResult::WaitingForInput);
+    computer
+}
+
+/// Explores the whole map forking the droid for every move, returns the number of open tiles.
+fn explore<M: Memory<Word = isize>>(droid: &Computer<M>) -> usize {
+    let mut visited = HashSet::new();
+    visited.insert((0, 0));
+    let mut queue = VecDeque::new();
+    queue.push_back(((0, 0), droid.clone()));
+    let mut open = 1;
+    while let Some(((x, y), computer)) = queue.pop_front() {
+        for (command, (dx, dy)) in [(1, (0, 1)), (2, (0, -1)), (3, (-1, 0)), (4, (1, 0))] {
+            let position = (x + dx, y + dy);
+            if !visited.insert(position) {
+                continue;
+            }
+            let mut fork = computer.clone();
+            match fork.run(Some(command)).unwrap() {
+                RunResult::Output(0) => {}
+                RunResult::Output(_) => {
+                    assert_eq!(fork.run(None).unwrap(), RunResult::WaitingForInput);
+                    open += 1;
+                    queue.push_back((position, fork));
+                }
+                result => panic!("unexpected {:?}", result),
+            }
+        }
+    }
+    open
+}
+
+/// Forks the droid with its memory grown to `size` cells, as if it used them.
+fn bench_grown<M: Memory<Word = isize>>(
+    group: &mut BenchmarkGroup<WallTime>,
+    name: &str,
+    droid: &Computer<M>,
+    size: usize,
+) {
+    let mut grown = droid.clone();
+    *grown.get_mem_mut(size - 1) = 0;
+    group.bench_with_input(BenchmarkId::new(name, size), &grown, |b, grown| {
+        b.iter(|| {
+            let mut fork = grown.clone();
+            *fork.get_mem_mut(1040) += 1;
+            fork
+        })
+    });
+}
+
+fn bench_fork(c: &mut Criterion) {
+    let paged: Computer = droid();
+    let dense: Computer<DenseMemory> = droid();
+
+    let mut group = c.benchmark_group("fork");
+    for size in [paged.mem_len(), 1 << 16, 1 << 20] {
+        bench_grown(&mut group, "paged", &paged, size);
+        bench_grown(&mut group, "dense", &dense, size);
+    }
+    group.finish();
+
+    c.bench_function("explore/day15/paged", |b| {
+        b.iter(|| explore(black_box(&paged)))
+    });
+    c.bench_function("explore/day15/dense", |b| {
+        b.iter(|| explore(black_box(&dense)))
+    });
+}
+
+criterion_group!(benches, bench_fork);
+criterion_main!(benches);
@@ -0,0 +1,48 @@
+//! Interpreter speed with and without the decoded-instruction cache: Day 09
+//! BOOST in sensor boost mode (one long running program) and the Day 07
+//! feedback loop search (many short runs of cloned amplifiers), and Day 09
+//! on the compiled engine.
+
+use advent_of_code_2019::intcode::compiled::Compiled;
+use advent_of_code_2019::intcode::{amplifiers, Computer, Engine};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+const DAY07: &str = include_str!("../inputs/day07.txt");
+const DAY09: &str = include_str!("../inputs/day09.txt");
+
+fn program(source: &str, cached: bool) -> Computer {
+    let computer: Computer = source.trim().parse().unwrap();
+    if cached {
+        computer.with_decode_cache()
+    } else {
+        computer
+    }
+}
+
+fn bench_interpreter(c: &mut Criterion) {
+    for (name, cached) in [("plain", false), ("cached", true)] {
+        let boost = program(DAY09, cached);
+        c.bench_function(&format!("day09/boost/{}", name), |b| {
+            b.iter(|| {
+                let mut computer = black_box(&boost).clone();
+                computer.run_with_constant_input(2).unwrap()
+            })
+        });
+
+        let amplifier = program(DAY07, cached);
+        c.bench_function(&format!("day07/feedback/{}", name), |b| {
+            b.iter(|| amplifiers::search(black_box(&amplifier), &[5, 6, 7, 8, 9], true).unwrap())
+        });
+    }
+
+    let boost = program(DAY09, false);
+    c.bench_function("day09/boost/compiled", |b| {
+        b.iter(|| {
+            let mut compiled = Compiled::new(black_box(&boost).clone());
+            compiled.run_with_constant_input(2).unwrap()
+        })
+    });
+}
+
+criterion_group!(benches, bench_interpreter);
+criterion_main!(benches);
@@ -1,7 +1,5 @@
-use std::collections::HashMap;
-
-use advent_of_code_2019::intcode::{Computer, RunResult};
-use aoc_helpers::permutations::permutations;
+use advent_of_code_2019::intcode::amplifiers;
+use advent_of_code_2019::intcode::Computer;
 use aoc_helpers::prelude::*;
 
 struct Day07;
@@ -12,63 +10,23 @@ impl Problem for Day07 {
     type Part2 = isize;
 
     fn solve_part1(input: &<Self::Input as aoc_helpers::scaffold::Parse>::Parsed) -> Self::Part1 {
-        let mut cache: HashMap<(isize, isize), isize> = Default::default();
-        let mut run = |phase: isize, inp: isize| -> isize {
-            *cache.entry((phase, inp)).or_insert_with(|| {
-                let mut c: Computer = input.as_slice().into();
-                match c.run(Some(phase)).expect("should parse") {
-                    RunResult::Finished => panic!(),
-                    RunResult::WaitingForInput | RunResult::Output(_) => {}
-                };
-                if let RunResult::Output(output) = c.run(Some(inp)).expect("should parse") {
-                    return output;
-                }
-                panic!()
-            })
-        };
-        let mut best = isize::MIN;
-        permutations(vec![0, 1, 2, 3, 4], |phases| {
-            let output = phases
-                .iter()
-                .fold(0, |prev_output, phase| run(*phase, prev_output));
-            if best < output {
-                best = output;
-            }
-        });
-        best
+        best_signal(input, &[0, 1, 2, 3, 4], false)
     }
 
     fn solve_part2(input: &<Self::Input as aoc_helpers::scaffold::Parse>::Parsed) -> Self::Part2 {
-        let init_amp = |phase: &isize| -> Computer {
-            let mut c: Computer = input.as_slice().into();
-            match c.run(Some(*phase)).expect("should parse") {
-                RunResult::Finished | RunResult::Output(_) => panic!(),
-                RunResult::WaitingForInput => c,
-            }
-        };
-
-        let mut best = isize::MIN;
-        permutations(vec![5, 6, 7, 8, 9], |phases| {
-            let mut amps: Vec<Computer> = phases.iter().map(init_amp).collect();
-            let mut output = 0;
-            let mut done = false;
-            while !done {
-                for amp in &mut amps {
-                    match amp.run(Some(output)).expect("should parse") {
-                        RunResult::Finished => done = true,
-                        RunResult::WaitingForInput => panic!(),
-                        RunResult::Output(new_output) => output = new_output,
-                    }
-                }
-            }
-            if best < output {
-                best = output;
-            }
-        });
-        best
+        best_signal(input, &[5, 6, 7, 8, 9], true)
     }
 }
 
+fn best_signal(program: &[isize], phases: &[isize], feedback: bool) -> isize {
+    let program: Computer = program.into();
+    amplifiers::search(&program, phases, feedback)
+        .expect("amplifiers should halt")
+        .best()
+        .and_then(|permutation| permutation.signal)
+        .expect("amplifiers should output")
+}
+
 fn main() {
     solve::<Day07>(include_str!("../../inputs/day07.txt"));
 }
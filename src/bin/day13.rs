@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 
 use advent_of_code_2019::intcode::{Computer, RunResult};
 use aoc_helpers::prelude::*;
@@ -6,6 +6,8 @@ use aoc_helpers::prelude::*;
 struct Day13;
 
 const WITH_DISPLAY: bool = false;
+/// Prints where the game spends its instructions once it's over.
+const WITH_PROFILE: bool = false;
 
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 enum Tile {
@@ -74,20 +76,21 @@ impl Problem for Day13 {
 
     fn solve_part1(input: &<Self::Input as aoc_helpers::scaffold::Parse>::Parsed) -> Self::Part1 {
         let mut c: Computer = input.as_slice().into();
-        let mut map: HashMap<(isize, isize), Tile> = Default::default();
-        loop {
-            match (c.run(None), c.run(None), c.run(None)) {
+        let mut output = Vec::new();
+        assert_eq!(
+            c.run_with_io(VecDeque::new(), &mut output)
+                .expect("invalid program"),
+            RunResult::Finished
+        );
+        let map: HashMap<(isize, isize), Tile> = output
+            .chunks(3)
+            .map(|chunk| {
                 (
-                    Ok(RunResult::Output(x)),
-                    Ok(RunResult::Output(y)),
-                    Ok(RunResult::Output(tile_id)),
-                ) => {
-                    map.insert((x, y), tile_id.try_into().expect("invalid tile"));
-                }
-                (Ok(RunResult::Finished), _, _) => break,
-                _ => panic!("invalid program"),
-            }
-        }
+                    (chunk[0], chunk[1]),
+                    chunk[2].try_into().expect("invalid tile"),
+                )
+            })
+            .collect();
         map.into_iter()
             .filter(|(_, tile)| *tile == Tile::Block)
             .count()
@@ -98,8 +101,12 @@ impl Problem for Day13 {
         let mut ball_x: isize = 0;
         let mut paddle_x: isize = 0;
 
-        let mut c: Computer = input.as_slice().into();
+        let program: Computer = input.as_slice().into();
+        let mut c = program.clone();
         *c.get_mem_mut(0) = 2;
+        if WITH_PROFILE {
+            c = c.with_profiling();
+        }
         let mut map: HashMap<(isize, isize), Tile> = Default::default();
         loop {
             match (
@@ -135,6 +142,9 @@ impl Problem for Day13 {
                 _ => panic!("invalid program"),
             }
         }
+        if let Some(profile) = c.profile() {
+            eprint!("{}", profile.report(&program, 10));
+        }
         last_score
     }
 }
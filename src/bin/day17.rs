@@ -1,4 +1,5 @@
-use advent_of_code_2019::intcode::{Computer, RunResult};
+use advent_of_code_2019::intcode::ascii::Ascii;
+use advent_of_code_2019::intcode::Computer;
 use aoc_helpers::prelude::*;
 
 struct Day17;
@@ -9,18 +10,14 @@ impl Problem for Day17 {
     type Part2 = usize;
 
     fn solve_part1(input: &<Self::Input as aoc_helpers::scaffold::Parse>::Parsed) -> Self::Part1 {
-        let mut camera_output = String::new();
-        let mut c = input.clone();
-        loop {
-            match c.run(None).expect("program should be correct") {
-                RunResult::Finished => break,
-                RunResult::WaitingForInput => panic!(),
-                RunResult::Output(output) => camera_output.push(output as u8 as char),
-            }
-        }
+        let camera_output = Ascii::new(input.clone())
+            .run()
+            .expect("program should be correct");
+        assert!(camera_output.finished);
 
         let map: Vec<Vec<bool>> = camera_output
-            .lines()
+            .lines
+            .iter()
             .map(|line| line.chars().map(|c| c != '.').collect())
             .collect();
         let mut alignment = 0;
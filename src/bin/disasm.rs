@@ -0,0 +1,20 @@
+use advent_of_code_2019::intcode::{analysis::analyze, disasm::disassemble, load};
+use aoc_helpers::anyhow;
+
+fn main() -> Result<(), anyhow::Error> {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let dot = args.first().is_some_and(|arg| arg == "--dot");
+    if dot {
+        args.remove(0);
+    }
+    let program = args
+        .first()
+        .ok_or_else(|| anyhow::anyhow!("usage: disasm [--dot] <dayNN | path>"))?;
+    let computer = load(program)?;
+    if dot {
+        print!("{}", analyze(&computer).to_dot());
+    } else {
+        print!("{}", disassemble(&computer));
+    }
+    Ok(())
+}
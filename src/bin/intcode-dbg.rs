@@ -0,0 +1,377 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+
+use advent_of_code_2019::intcode::{load, Computer, IntcodeError, StepResult};
+use aoc_helpers::anyhow;
+
+const HELP: &str = "\
+commands:
+  b|break <addr>       set a breakpoint
+  d|delete <addr>      remove a breakpoint
+  w|watch <addr>       stop whenever the memory cell changes
+  unwatch <addr>       remove a watchpoint
+  s|step [n]           execute n instructions (default 1)
+  c|continue           run until breakpoint, watchpoint, missing input or halt
+  f|finish             run until the current frame is released
+                       (relative base drops below its current value)
+  r|regs               show instruction pointer and relative base
+  x <addr> [len]       dump memory around an address (default 32 cells)
+  set <addr> <value>   overwrite a memory cell
+  i|input <v>...       queue input values
+  q|quit               exit";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Command {
+    Break(usize),
+    Delete(usize),
+    Watch(usize),
+    Unwatch(usize),
+    Step(usize),
+    Continue,
+    Finish,
+    Registers,
+    Dump(usize, usize),
+    Set(usize, isize),
+    Input(Vec<isize>),
+    Help,
+    Quit,
+}
+
+impl FromStr for Command {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let name = words.next().unwrap_or("help");
+        let args: Vec<&str> = words.collect();
+        let arg = |idx: usize| -> Result<&str, anyhow::Error> {
+            args.get(idx)
+                .copied()
+                .ok_or_else(|| anyhow::anyhow!("{:?} is missing argument {}", name, idx + 1))
+        };
+        let address = |idx: usize| -> Result<usize, anyhow::Error> {
+            let arg = arg(idx)?;
+            arg.parse()
+                .map_err(|err| anyhow::anyhow!("Invalid address {:?}: {}", arg, err))
+        };
+        let value = |arg: &str| -> Result<isize, anyhow::Error> {
+            arg.parse()
+                .map_err(|err| anyhow::anyhow!("Invalid value {:?}: {}", arg, err))
+        };
+        Ok(match name {
+            "b" | "break" => Self::Break(address(0)?),
+            "d" | "delete" => Self::Delete(address(0)?),
+            "w" | "watch" => Self::Watch(address(0)?),
+            "unwatch" => Self::Unwatch(address(0)?),
+            "s" | "step" => Self::Step(if args.is_empty() { 1 } else { address(0)? }),
+            "c" | "continue" => Self::Continue,
+            "f" | "finish" => Self::Finish,
+            "r" | "regs" => Self::Registers,
+            "x" => Self::Dump(address(0)?, if args.len() > 1 { address(1)? } else { 32 }),
+            "set" => Self::Set(address(0)?, value(arg(1)?)?),
+            "i" | "input" => Self::Input(
+                args.iter()
+                    .map(|arg| value(arg))
+                    .collect::<Result<_, _>>()?,
+            ),
+            "h" | "help" => Self::Help,
+            "q" | "quit" => Self::Quit,
+            _ => return Err(anyhow::anyhow!("Unknown command: {:?}", name)),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Stop {
+    Stepped,
+    Breakpoint(usize),
+    Watchpoint {
+        address: usize,
+        old: isize,
+        new: isize,
+    },
+    FrameReleased,
+    WaitingForInput,
+    Finished,
+    Fault(IntcodeError),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Until {
+    Steps(usize),
+    Stop,
+    FrameReleased(isize),
+}
+
+struct Debugger {
+    computer: Computer,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: BTreeSet<usize>,
+    input: VecDeque<isize>,
+    output: Vec<isize>,
+}
+
+impl Debugger {
+    fn new(computer: Computer) -> Self {
+        Self {
+            computer,
+            breakpoints: Default::default(),
+            watchpoints: Default::default(),
+            input: Default::default(),
+            output: Default::default(),
+        }
+    }
+
+    fn resume(&mut self, until: Until) -> Stop {
+        let mut steps = 0;
+        loop {
+            if let Until::Steps(limit) = until {
+                if steps == limit {
+                    return Stop::Stepped;
+                }
+            }
+            if steps > 0 && self.breakpoints.contains(&self.computer.ip()) {
+                return Stop::Breakpoint(self.computer.ip());
+            }
+
+            let event = match self
+                .computer
+                .step_with_io(&mut self.input, &mut self.output)
+            {
+                Ok(event) => event,
+                Err(err) => return Stop::Fault(err.into_fault()),
+            };
+            steps += 1;
+
+            match event.result {
+                StepResult::Executed | StepResult::Output(_) => {}
+                StepResult::WaitingForInput => return Stop::WaitingForInput,
+                StepResult::Finished => return Stop::Finished,
+            }
+            if let Some(write) = event.write {
+                if self.watchpoints.contains(&write.address) && write.old != write.new {
+                    return Stop::Watchpoint {
+                        address: write.address,
+                        old: write.old,
+                        new: write.new,
+                    };
+                }
+            }
+            if let Until::FrameReleased(relative_base) = until {
+                if self.computer.relative_base() < relative_base {
+                    return Stop::FrameReleased;
+                }
+            }
+        }
+    }
+
+    fn location(&self) -> String {
+        let ip = self.computer.ip();
+        let words: Vec<String> = (ip..ip.saturating_add(4))
+            .map(|idx| self.computer.get_mem(idx).to_string())
+            .collect();
+        format!("{:04}: {}", ip, words.join(","))
+    }
+
+    fn dump(&self, address: usize, len: usize) -> String {
+        const ROW: usize = 8;
+        let start = address.saturating_sub(len / 2) / ROW * ROW;
+        // inclusive, so that the last cell of the address space shows up
+        let end = start.saturating_add(len.max(1) - 1);
+        let mut lines = Vec::new();
+        for row in (start..=end).step_by(ROW) {
+            let cells: Vec<String> = (row..=row.saturating_add(ROW - 1))
+                .map(|idx| {
+                    let marker = if idx == address { '>' } else { ' ' };
+                    format!("{}{:>8}", marker, self.computer.get_mem(idx))
+                })
+                .collect();
+            lines.push(format!("{:04}:{}", row, cells.join("")));
+        }
+        lines.join("\n")
+    }
+
+    /// Executes a command, returns `None` when the session should end.
+    fn execute(&mut self, command: Command) -> Option<String> {
+        let until = match command {
+            Command::Break(address) => {
+                self.breakpoints.insert(address);
+                return Some(format!("breakpoint at {}", address));
+            }
+            Command::Delete(address) => {
+                self.breakpoints.remove(&address);
+                return Some(format!("deleted breakpoint at {}", address));
+            }
+            Command::Watch(address) => {
+                self.watchpoints.insert(address);
+                return Some(format!("watching {}", address));
+            }
+            Command::Unwatch(address) => {
+                self.watchpoints.remove(&address);
+                return Some(format!("stopped watching {}", address));
+            }
+            Command::Registers => {
+                return Some(format!(
+                    "ip = {}\nrelative base = {}\n{}",
+                    self.computer.ip(),
+                    self.computer.relative_base(),
+                    self.location()
+                ))
+            }
+            Command::Dump(address, len) => return Some(self.dump(address, len)),
+            Command::Set(address, value) => {
+                return Some(match self.computer.set_mem(address, value) {
+                    Ok(()) => format!("[{}] = {}", address, value),
+                    Err(err) => format!("can't set [{}]: {}", address, err),
+                });
+            }
+            Command::Input(values) => {
+                self.input.extend(values);
+                return Some(format!("{} input value(s) queued", self.input.len()));
+            }
+            Command::Help => return Some(HELP.to_owned()),
+            Command::Quit => return None,
+            Command::Step(steps) => Until::Steps(steps),
+            Command::Continue => Until::Stop,
+            Command::Finish => Until::FrameReleased(self.computer.relative_base()),
+        };
+
+        let outputs_before = self.output.len();
+        let stop = self.resume(until);
+        let mut report: Vec<String> = self.output[outputs_before..]
+            .iter()
+            .map(|output| format!("output: {}", output))
+            .collect();
+        report.push(match stop {
+            Stop::Stepped | Stop::FrameReleased => self.location(),
+            Stop::Breakpoint(address) => format!("breakpoint at {}\n{}", address, self.location()),
+            Stop::Watchpoint { address, old, new } => format!(
+                "watchpoint [{}]: {} -> {}\n{}",
+                address,
+                old,
+                new,
+                self.location()
+            ),
+            Stop::WaitingForInput => format!("waiting for input\n{}", self.location()),
+            Stop::Finished => "program finished".to_owned(),
+            Stop::Fault(err) => format!("fault: {}\n{}", err, self.location()),
+        });
+        Some(report.join("\n"))
+    }
+}
+
+fn main() -> Result<(), anyhow::Error> {
+    let program = std::env::args()
+        .nth(1)
+        .ok_or_else(|| anyhow::anyhow!("usage: intcode-dbg <dayNN | path>"))?;
+    let mut debugger = Debugger::new(load(&program)?);
+    println!("{}", debugger.location());
+
+    let stdin = std::io::stdin();
+    loop {
+        print!("(icdb) ");
+        std::io::stdout().flush()?;
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            break;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        match line.parse::<Command>() {
+            Ok(command) => match debugger.execute(command) {
+                Some(report) => println!("{}", report),
+                None => break,
+            },
+            Err(err) => println!("{}", err),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commands() {
+        assert_eq!("b 12".parse::<Command>().unwrap(), Command::Break(12));
+        assert_eq!("s".parse::<Command>().unwrap(), Command::Step(1));
+        assert_eq!("x 40".parse::<Command>().unwrap(), Command::Dump(40, 32));
+        assert_eq!(
+            "input 1 -2".parse::<Command>().unwrap(),
+            Command::Input(vec![1, -2])
+        );
+        assert!("b".parse::<Command>().is_err());
+        assert!("jump 3".parse::<Command>().is_err());
+    }
+
+    #[test]
+    fn test_breakpoints_and_watchpoints() {
+        // sums two inputs into [13] and outputs it
+        let mut debugger = Debugger::new("3,13,3,14,1,13,14,13,4,13,99,0,0,0,0".parse().unwrap());
+        debugger.breakpoints.insert(4);
+        debugger.watchpoints.insert(13);
+        assert_eq!(debugger.resume(Until::Stop), Stop::WaitingForInput);
+        debugger.input.extend([3, 4]);
+        assert_eq!(
+            debugger.resume(Until::Stop),
+            Stop::Watchpoint {
+                address: 13,
+                old: 0,
+                new: 3
+            }
+        );
+        assert_eq!(debugger.resume(Until::Stop), Stop::Breakpoint(4));
+        assert_eq!(
+            debugger.resume(Until::Stop),
+            Stop::Watchpoint {
+                address: 13,
+                old: 3,
+                new: 7
+            }
+        );
+        assert_eq!(debugger.resume(Until::Steps(1)), Stop::Stepped);
+        assert_eq!(debugger.output, vec![7]);
+        assert_eq!(debugger.resume(Until::Stop), Stop::Finished);
+    }
+
+    #[test]
+    fn test_finish() {
+        // allocate a frame, release it and halt
+        let mut debugger = Debugger::new("109,5,1101,1,1,0,109,-5,99".parse().unwrap());
+        debugger.resume(Until::Steps(1));
+        assert_eq!(
+            debugger.resume(Until::FrameReleased(debugger.computer.relative_base())),
+            Stop::FrameReleased
+        );
+        assert_eq!(debugger.computer.ip(), 8);
+    }
+
+    #[test]
+    fn test_end_of_address_space() {
+        let debugger = Debugger::new("99".parse().unwrap());
+        let dump = debugger.dump(usize::MAX, 32);
+        assert_eq!(dump.lines().count(), 3);
+        assert!(dump.ends_with(&format!(
+            "{}:{}>       0",
+            usize::MAX - 7,
+            "        0".repeat(7)
+        )));
+
+        let mut debugger = Debugger::new("99".parse().unwrap());
+        assert_eq!(
+            debugger.execute(Command::Set(usize::MAX, 1)).unwrap(),
+            format!(
+                "can't set [{}]: memory limit exceeded writing to {0} (instruction 99 at 0)",
+                usize::MAX
+            )
+        );
+        assert!(debugger
+            .execute(Command::Set(100_000_000_000, 1))
+            .unwrap()
+            .starts_with("can't set [100000000000]: memory limit exceeded"));
+        assert_eq!(debugger.execute(Command::Set(3, 1)).unwrap(), "[3] = 1");
+    }
+}
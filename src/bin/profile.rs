@@ -0,0 +1,54 @@
+use advent_of_code_2019::intcode::io::{FromFn, ToFn};
+use advent_of_code_2019::intcode::{load, RunResult};
+use aoc_helpers::anyhow;
+
+const USAGE: &str = "usage: profile [--set <addr>=<value>]... <dayNN | path> [input]...
+inputs are fed in order, the last one for as long as the program asks";
+
+/// Hottest blocks and instructions to report.
+const TOP: usize = 10;
+
+fn main() -> Result<(), anyhow::Error> {
+    let mut args = std::env::args().skip(1).peekable();
+    let mut pokes = Vec::new();
+    while args.peek().is_some_and(|arg| arg == "--set") {
+        args.next();
+        let poke = args.next().ok_or_else(|| anyhow::anyhow!(USAGE))?;
+        let (address, value) = poke
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("Invalid --set {:?}, expected <addr>=<value>", poke))?;
+        pokes.push((address.parse::<usize>()?, value.parse::<isize>()?));
+    }
+    let program = load(&args.next().ok_or_else(|| anyhow::anyhow!(USAGE))?)?;
+    let inputs = args
+        .map(|arg| arg.parse::<isize>())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut computer = program.clone();
+    for (address, value) in pokes {
+        computer.set_mem(address, value)?;
+    }
+    let mut computer = computer.with_profiling();
+    let mut inputs = inputs.iter().copied();
+    let mut last_input = None;
+    let mut outputs = 0;
+    let result = computer.run_with_io(
+        FromFn(|| {
+            last_input = inputs.next().or(last_input);
+            last_input
+        }),
+        ToFn(|_| outputs += 1),
+    )?;
+    if result == RunResult::WaitingForInput {
+        eprintln!("stopped, the program asks for input");
+    }
+    println!("{} outputs", outputs);
+    print!(
+        "{}",
+        computer
+            .profile()
+            .expect("profiling is on")
+            .report(&program, TOP)
+    );
+    Ok(())
+}
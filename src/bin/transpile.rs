@@ -0,0 +1,81 @@
+use std::time::Instant;
+
+use advent_of_code_2019::intcode::transpile::{build, run_built, transpile};
+use advent_of_code_2019::intcode::{load, Computer, RunResult};
+use aoc_helpers::anyhow;
+
+const USAGE: &str =
+    "usage: transpile <dayNN | path> > program.rs\n       transpile --compare <dayNN | path> [input...]";
+
+/// Outputs of the interpreter given `inputs`.
+fn interpret(mut computer: Computer, inputs: &[isize]) -> Result<Vec<isize>, anyhow::Error> {
+    let mut inputs = inputs.iter().copied();
+    let mut input = None;
+    let mut outputs = Vec::new();
+    loop {
+        match computer.run(input.take())? {
+            RunResult::Output(output) => outputs.push(output),
+            RunResult::WaitingForInput => {
+                input = Some(
+                    inputs
+                        .next()
+                        .ok_or_else(|| anyhow::anyhow!("Out of input"))?,
+                )
+            }
+            RunResult::Finished => return Ok(outputs),
+        }
+    }
+}
+
+/// Runs the program with both the interpreter and its transpiled build,
+/// checking that the outputs match and reporting the run times. The time of
+/// the build includes starting the process.
+fn compare(program: &str, inputs: &[isize]) -> Result<(), anyhow::Error> {
+    let computer = load(program)?;
+    let dir = std::env::temp_dir().join(format!("intcode-transpile-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let built = build(&computer, &dir, "program");
+
+    let started = Instant::now();
+    let expected = interpret(computer, inputs)?;
+    let interpreted = started.elapsed();
+    let started = Instant::now();
+    let actual = built.and_then(|executable| run_built(&executable, inputs));
+    let transpiled = started.elapsed();
+    std::fs::remove_dir_all(&dir)?;
+
+    let actual = actual?;
+    if actual != expected {
+        return Err(anyhow::anyhow!(
+            "Outputs differ:\n  interpreted: {:?}\n  transpiled:  {:?}",
+            expected,
+            actual
+        ));
+    }
+    println!("outputs: {:?}", actual);
+    println!("interpreted: {:?}", interpreted);
+    println!("transpiled:  {:?}", transpiled);
+    Ok(())
+}
+
+fn main() -> Result<(), anyhow::Error> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.as_slice() {
+        [flag, program, inputs @ ..] if flag == "--compare" => {
+            let inputs = inputs
+                .iter()
+                .map(|input| {
+                    input.parse().map_err(|err| {
+                        anyhow::anyhow!("Parsing {:?} to int failed: {}", input, err)
+                    })
+                })
+                .collect::<Result<Vec<isize>, _>>()?;
+            compare(program, &inputs)
+        }
+        [program] => {
+            print!("{}", transpile(&load(program)?));
+            Ok(())
+        }
+        _ => Err(anyhow::anyhow!(USAGE)),
+    }
+}
@@ -1,6 +1,147 @@
+use std::fmt;
 use std::str::FromStr;
+use std::time::Instant;
 
 use aoc_helpers::anyhow;
+use serde::{Deserialize, Serialize};
+
+use self::memory::{Memory, PagedMemory};
+use self::word::Word;
+
+pub mod amplifiers;
+pub mod analysis;
+pub mod ascii;
+pub mod asm;
+pub mod asynchronous;
+pub mod cache;
+pub mod compiled;
+pub mod differential;
+pub mod disasm;
+#[cfg(test)]
+mod fuzz;
+pub mod io;
+pub mod memory;
+pub mod network;
+pub mod packet;
+pub mod profile;
+pub mod selfmod;
+pub mod snapshot;
+pub mod trace;
+pub mod transpile;
+pub mod word;
+
+/// Failure to decode an instruction word into an [`Instruction`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    UnknownOpcode(isize),
+    UnknownMode(isize),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownOpcode(opcode) => write!(f, "Unknown opcode: {}", opcode),
+            Self::UnknownMode(mode) => write!(f, "Unknown mode: {}", mode),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Fault raised while executing a program.
+///
+/// Every variant carries the instruction pointer (`ip`) and the raw word of
+/// the instruction that faulted, so the machine state can be inspected (and
+/// the program possibly patched and resumed) afterwards. Words wider than
+/// `isize` are reported clamped to its range.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntcodeError {
+    UnknownOpcode {
+        ip: usize,
+        instruction: isize,
+    },
+    UnknownMode {
+        ip: usize,
+        instruction: isize,
+    },
+    WriteToImmediate {
+        ip: usize,
+        instruction: isize,
+    },
+    NegativeAddress {
+        ip: usize,
+        instruction: isize,
+        address: isize,
+    },
+    ArithmeticOverflow {
+        ip: usize,
+        instruction: isize,
+    },
+    /// An address or jump target wider than `isize`.
+    AddressOutOfRange {
+        ip: usize,
+        instruction: isize,
+    },
+    MemoryLimit {
+        ip: usize,
+        instruction: isize,
+        address: usize,
+    },
+}
+
+impl IntcodeError {
+    fn from_decode(ip: usize, instruction: isize, err: DecodeError) -> Self {
+        match err {
+            DecodeError::UnknownOpcode(_) => Self::UnknownOpcode { ip, instruction },
+            DecodeError::UnknownMode(_) => Self::UnknownMode { ip, instruction },
+        }
+    }
+
+    pub fn ip(&self) -> usize {
+        match self {
+            Self::UnknownOpcode { ip, .. }
+            | Self::UnknownMode { ip, .. }
+            | Self::WriteToImmediate { ip, .. }
+            | Self::NegativeAddress { ip, .. }
+            | Self::ArithmeticOverflow { ip, .. }
+            | Self::AddressOutOfRange { ip, .. }
+            | Self::MemoryLimit { ip, .. } => *ip,
+        }
+    }
+
+    pub fn instruction(&self) -> isize {
+        match self {
+            Self::UnknownOpcode { instruction, .. }
+            | Self::UnknownMode { instruction, .. }
+            | Self::WriteToImmediate { instruction, .. }
+            | Self::NegativeAddress { instruction, .. }
+            | Self::ArithmeticOverflow { instruction, .. }
+            | Self::AddressOutOfRange { instruction, .. }
+            | Self::MemoryLimit { instruction, .. } => *instruction,
+        }
+    }
+}
+
+impl fmt::Display for IntcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownOpcode { .. } => write!(f, "unknown opcode")?,
+            Self::UnknownMode { .. } => write!(f, "unknown parameter mode")?,
+            Self::WriteToImmediate { .. } => write!(f, "write to an immediate mode parameter")?,
+            Self::NegativeAddress { address, .. } => {
+                write!(f, "access to negative address {}", address)?
+            }
+            Self::ArithmeticOverflow { .. } => write!(f, "arithmetic overflow")?,
+            Self::AddressOutOfRange { .. } => write!(f, "address out of range")?,
+            Self::MemoryLimit { address, .. } => {
+                write!(f, "memory limit exceeded writing to {}", address)?
+            }
+        }
+        write!(f, " (instruction {} at {})", self.instruction(), self.ip())
+    }
+}
+
+impl std::error::Error for IntcodeError {}
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Opcode {
@@ -16,8 +157,81 @@ pub enum Opcode {
     Halt,
 }
 
+impl Opcode {
+    pub const ALL: [Self; 10] = [
+        Self::Add,
+        Self::Mul,
+        Self::Input,
+        Self::Output,
+        Self::JumpIfTrue,
+        Self::JumpIfFalse,
+        Self::LessThan,
+        Self::Equals,
+        Self::AdjustRelativeBase,
+        Self::Halt,
+    ];
+
+    /// Number of parameters following the instruction word.
+    pub fn arity(&self) -> usize {
+        match self {
+            Self::Add | Self::Mul | Self::LessThan | Self::Equals => 3,
+            Self::JumpIfTrue | Self::JumpIfFalse => 2,
+            Self::Input | Self::Output | Self::AdjustRelativeBase => 1,
+            Self::Halt => 0,
+        }
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::Add => "ADD",
+            Self::Mul => "MUL",
+            Self::Input => "IN",
+            Self::Output => "OUT",
+            Self::JumpIfTrue => "JT",
+            Self::JumpIfFalse => "JF",
+            Self::LessThan => "LT",
+            Self::Equals => "EQ",
+            Self::AdjustRelativeBase => "ARB",
+            Self::Halt => "HLT",
+        }
+    }
+
+    /// Looks up an opcode by its (case insensitive) mnemonic.
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|opcode| opcode.mnemonic().eq_ignore_ascii_case(mnemonic))
+    }
+
+    /// Index of the parameter the instruction writes to.
+    pub fn written_param(&self) -> Option<usize> {
+        match self {
+            Self::Add | Self::Mul | Self::LessThan | Self::Equals => Some(2),
+            Self::Input => Some(0),
+            _ => None,
+        }
+    }
+}
+
+impl From<Opcode> for isize {
+    fn from(opcode: Opcode) -> Self {
+        match opcode {
+            Opcode::Add => 1,
+            Opcode::Mul => 2,
+            Opcode::Input => 3,
+            Opcode::Output => 4,
+            Opcode::JumpIfTrue => 5,
+            Opcode::JumpIfFalse => 6,
+            Opcode::LessThan => 7,
+            Opcode::Equals => 8,
+            Opcode::AdjustRelativeBase => 9,
+            Opcode::Halt => 99,
+        }
+    }
+}
+
 impl TryFrom<isize> for Opcode {
-    type Error = anyhow::Error;
+    type Error = DecodeError;
 
     fn try_from(value: isize) -> Result<Self, Self::Error> {
         match value {
@@ -31,7 +245,7 @@ impl TryFrom<isize> for Opcode {
             8 => Ok(Self::Equals),
             9 => Ok(Self::AdjustRelativeBase),
             99 => Ok(Self::Halt),
-            _ => Err(anyhow::anyhow!("Unknown opcode: {}", value)),
+            _ => Err(DecodeError::UnknownOpcode(value)),
         }
     }
 }
@@ -44,36 +258,24 @@ pub enum Mode {
 }
 
 impl TryFrom<isize> for Mode {
-    type Error = anyhow::Error;
+    type Error = DecodeError;
 
     fn try_from(value: isize) -> Result<Self, Self::Error> {
         match value {
             0 => Ok(Self::Position),
             1 => Ok(Self::Immediate),
             2 => Ok(Self::Relative),
-            _ => Err(anyhow::anyhow!("Unknown mode: {}", value)),
+            _ => Err(DecodeError::UnknownMode(value)),
         }
     }
 }
 
-impl Mode {
-    fn get(&self, computer: &Computer, offset: usize) -> isize {
-        let immediate = computer.get_mem(computer.idx + offset);
-        match self {
-            Mode::Position => computer.get_mem(immediate as usize),
-            Mode::Immediate => immediate,
-            Mode::Relative => computer.get_mem((computer.relative_base + immediate) as usize),
-        }
-    }
-
-    fn get_mut<'a>(&self, computer: &'a mut Computer, offset: usize) -> Option<&'a mut isize> {
-        let immediate = computer.get_mem(computer.idx + offset);
-        match self {
-            Mode::Position => Some(computer.get_mem_mut(immediate as usize)),
-            Mode::Immediate => None,
-            Mode::Relative => {
-                Some(computer.get_mem_mut((computer.relative_base + immediate) as usize))
-            }
+impl From<Mode> for isize {
+    fn from(mode: Mode) -> Self {
+        match mode {
+            Mode::Position => 0,
+            Mode::Immediate => 1,
+            Mode::Relative => 2,
         }
     }
 }
@@ -87,7 +289,7 @@ pub struct Instruction {
 }
 
 impl TryFrom<isize> for Instruction {
-    type Error = anyhow::Error;
+    type Error = DecodeError;
 
     fn try_from(value: isize) -> Result<Self, Self::Error> {
         let opcode = (value % 100).try_into()?;
@@ -104,129 +306,533 @@ impl TryFrom<isize> for Instruction {
 }
 
 impl Instruction {
-    fn execute(&self, computer: &mut Computer) {
-        match self.opcode {
-            Opcode::Input | Opcode::Output | Opcode::AdjustRelativeBase | Opcode::Halt => panic!(),
-            Opcode::Add => computer.mut_2args_into_3rd(self, |a, b| a + b),
-            Opcode::Mul => computer.mut_2args_into_3rd(self, |a, b| a * b),
-            Opcode::JumpIfTrue => computer.jump_if_1st_into_2nd(self, |a| a != 0),
-            Opcode::JumpIfFalse => computer.jump_if_1st_into_2nd(self, |a| a == 0),
-            Opcode::LessThan => computer.mut_2args_into_3rd(self, |a, b| if a < b { 1 } else { 0 }),
-            Opcode::Equals => computer.mut_2args_into_3rd(self, |a, b| if a == b { 1 } else { 0 }),
+    /// Instruction the machine reports when it runs past the end of its memory.
+    const END_OF_MEMORY: Self = Self {
+        opcode: Opcode::Halt,
+        arg1_mode: Mode::Position,
+        arg2_mode: Mode::Position,
+        arg3_mode: Mode::Position,
+    };
+
+    pub fn new(opcode: Opcode, modes: [Mode; 3]) -> Self {
+        let [arg1_mode, arg2_mode, arg3_mode] = modes;
+        Self {
+            opcode,
+            arg1_mode,
+            arg2_mode,
+            arg3_mode,
         }
     }
+
+    pub fn opcode(&self) -> Opcode {
+        self.opcode
+    }
+
+    pub fn modes(&self) -> [Mode; 3] {
+        [self.arg1_mode, self.arg2_mode, self.arg3_mode]
+    }
+}
+
+impl From<Instruction> for isize {
+    fn from(instruction: Instruction) -> Self {
+        isize::from(instruction.opcode)
+            + isize::from(instruction.arg1_mode) * 100
+            + isize::from(instruction.arg2_mode) * 1000
+            + isize::from(instruction.arg3_mode) * 10000
+    }
+}
+
+/// Parameter of an executed instruction resolved through its [`Mode`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Operand<W = isize> {
+    pub mode: Mode,
+    /// Parameter as stored in memory after the instruction word.
+    pub raw: W,
+    /// Address the parameter points at (`None` in immediate mode).
+    pub address: Option<usize>,
+    /// Value read through the parameter, for written parameters the value before the write.
+    pub value: W,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryWrite<W = isize> {
+    pub address: usize,
+    pub old: W,
+    pub new: W,
 }
 
-#[derive(Clone, Debug)]
-pub struct Computer {
-    mem: Vec<isize>,
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Jump {
+    /// Clamped to the `isize` range, taking a jump to a wider target faults.
+    pub target: isize,
+    pub taken: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepResult<W = isize> {
+    Executed,
+    WaitingForInput,
+    Output(W),
+    Finished,
+}
+
+/// Everything a single [`Computer::step`] did.
+///
+/// When the machine is waiting for input or has finished nothing is executed:
+/// `write` and `jump` are empty and the instruction pointer stays put.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StepEvent<W = isize> {
+    pub ip: usize,
+    pub instruction: Instruction,
+    pub operands: [Option<Operand<W>>; 3],
+    pub write: Option<MemoryWrite<W>>,
+    pub jump: Option<Jump>,
+    pub result: StepResult<W>,
+}
+
+/// Intcode machine, generic over its [`Memory`] backend and the [`Word`] type
+/// it stores, both picked when the program is loaded:
+/// `let c: Computer<SparseMemory<i128>> = program.parse()?`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Computer<M = PagedMemory> {
+    mem: M,
     idx: usize,
     relative_base: isize,
+    /// Maximum number of cells the program may make the memory backend hold.
+    #[serde(default)]
+    memory_limit: Option<usize>,
+    #[serde(skip)]
+    self_modification: Option<selfmod::Tracker>,
+    #[serde(skip)]
+    decode_cache: Option<cache::DecodeCache>,
+    #[serde(skip)]
+    profile: Option<profile::Profile>,
+}
+
+impl<M: Memory> From<&[M::Word]> for Computer<M> {
+    fn from(mem: &[M::Word]) -> Self {
+        mem.to_vec().into()
+    }
 }
 
-impl From<&[isize]> for Computer {
-    fn from(mem: &[isize]) -> Self {
+impl<M: Memory> From<Vec<M::Word>> for Computer<M> {
+    fn from(mem: Vec<M::Word>) -> Self {
         Self {
-            mem: mem.to_vec(),
+            mem: mem.into(),
             idx: 0,
             relative_base: 0,
+            memory_limit: None,
+            self_modification: None,
+            decode_cache: None,
+            profile: None,
         }
     }
 }
 
-impl FromStr for Computer {
+impl<M: Memory> FromStr for Computer<M>
+where
+    <M::Word as FromStr>::Err: fmt::Display,
+{
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mem: Vec<isize> = s
+        let mem: Vec<M::Word> = s
             .split(',')
             .map(|n| {
-                n.parse::<isize>()
+                n.parse::<M::Word>()
                     .map_err(|err| anyhow::anyhow!("Parsing {:?} to int failed: {}", n, err))
             })
             .collect::<Result<_, _>>()?;
-        Ok(Self {
-            mem,
-            idx: 0,
-            relative_base: 0,
-        })
+        Ok(mem.into())
     }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub enum RunResult {
+pub enum RunResult<W = isize> {
     Finished,
     WaitingForInput,
-    Output(isize),
+    Output(W),
+}
+
+/// Loads one of the bundled puzzle programs (`day13`) or a program from a path.
+pub fn load(program: &str) -> Result<Computer, anyhow::Error> {
+    let bundled = format!("{}/inputs/{}.txt", env!("CARGO_MANIFEST_DIR"), program);
+    let source = std::fs::read_to_string(bundled)
+        .or_else(|_| std::fs::read_to_string(program))
+        .map_err(|err| anyhow::anyhow!("Can't read {:?}: {}", program, err))?;
+    source.trim().parse()
 }
 
-impl Computer {
-    fn mut_2args_into_3rd<F: Fn(isize, isize) -> isize>(&mut self, instr: &Instruction, fun: F) {
-        let a = instr.arg1_mode.get(self, 1);
-        let b = instr.arg2_mode.get(self, 2);
-        let result = fun(a, b);
-        *instr.arg3_mode.get_mut(self, 3).unwrap() = result;
-        self.idx += 4;
+impl<M: Memory> Computer<M> {
+    /// Makes writes that would grow the memory past `cells` fault with
+    /// [`IntcodeError::MemoryLimit`] instead of allocating. Without it the
+    /// backend's [`Memory::DEFAULT_LIMIT`] applies, `usize::MAX` lifts it.
+    pub fn with_memory_limit(mut self, cells: usize) -> Self {
+        self.memory_limit = Some(cells);
+        self
+    }
+
+    pub fn ip(&self) -> usize {
+        self.idx
     }
 
-    fn jump_if_1st_into_2nd<F: Fn(isize) -> bool>(&mut self, instr: &Instruction, fun: F) {
-        let a = instr.arg1_mode.get(self, 1);
-        let b = instr.arg2_mode.get(self, 2);
-        self.idx = if fun(a) { b as usize } else { self.idx + 3 }
+    pub fn relative_base(&self) -> isize {
+        self.relative_base
     }
 
-    pub fn get_mem(&self, idx: usize) -> isize {
-        self.mem.get(idx).copied().unwrap_or_default()
+    fn operand(&self, mode: Mode, offset: usize) -> Result<Operand<M::Word>, IntcodeError> {
+        let raw = self.get_mem(self.idx + offset);
+        let address = match mode {
+            Mode::Position => Some(self.wide_address(&raw)?),
+            Mode::Immediate => None,
+            Mode::Relative => Some(self.wide_relative_address(&raw)?),
+        };
+        Ok(Operand {
+            mode,
+            value: address.map_or_else(|| raw.clone(), |address| self.get_mem(address)),
+            raw,
+            address,
+        })
     }
 
-    pub fn get_mem_mut(&mut self, idx: usize) -> &mut isize {
-        if self.mem.len() <= idx {
-            let additional = idx - self.mem.len() + 1;
-            self.mem.reserve(additional);
-            self.mem.extend(std::iter::repeat(0).take(additional));
+    fn write(
+        &mut self,
+        operand: &Operand<M::Word>,
+        value: M::Word,
+    ) -> Result<MemoryWrite<M::Word>, IntcodeError> {
+        let address = operand
+            .address
+            .ok_or_else(|| self.fault_write_to_immediate())?;
+        self.write_to(address, value)
+    }
+
+    fn write_to(
+        &mut self,
+        address: usize,
+        value: M::Word,
+    ) -> Result<MemoryWrite<M::Word>, IntcodeError> {
+        self.check_memory_limit(address)?;
+        if let Some(tracker) = &mut self.self_modification {
+            tracker.write(Some(self.idx), address);
+        }
+        if let Some(cache) = &mut self.decode_cache {
+            cache.invalidate(address);
         }
-        self.mem.get_mut(idx).expect("should be long enough")
+        let cell = self.mem.get_mut(address);
+        let old = std::mem::replace(cell, value.clone());
+        Ok(MemoryWrite {
+            address,
+            old,
+            new: value,
+        })
     }
 
-    pub fn run(&mut self, mut input: Option<isize>) -> Result<RunResult, anyhow::Error> {
-        while self.idx < self.mem.len() {
-            let instr: Instruction = self.mem[self.idx].try_into()?;
+    fn check_memory_limit(&self, address: usize) -> Result<(), IntcodeError> {
+        if self
+            .memory_limit
+            .or(M::DEFAULT_LIMIT)
+            .is_some_and(|limit| self.mem.footprint_after_write(address) > limit)
+        {
+            return Err(IntcodeError::MemoryLimit {
+                ip: self.idx,
+                instruction: self.faulting_instruction(),
+                address,
+            });
+        }
+        Ok(())
+    }
 
-            match instr.opcode {
-                Opcode::Input => {
-                    if let Some(input) = input.take() {
-                        *instr.arg1_mode.get_mut(self, 1).unwrap() = input;
-                        self.idx += 2;
-                    } else {
-                        return Ok(RunResult::WaitingForInput);
-                    }
-                }
-                Opcode::Output => {
-                    let output = instr.arg1_mode.get(self, 1);
-                    self.idx += 2;
-                    return Ok(RunResult::Output(output));
+    fn address(&self, address: isize) -> Result<usize, IntcodeError> {
+        usize::try_from(address).map_err(|_| IntcodeError::NegativeAddress {
+            ip: self.idx,
+            instruction: self.faulting_instruction(),
+            address,
+        })
+    }
+
+    /// Address `word` points at, faulting instead of clamping words wider
+    /// than `isize`.
+    fn wide_address(&self, word: &M::Word) -> Result<usize, IntcodeError> {
+        let address = word
+            .to_isize()
+            .ok_or_else(|| self.fault_address_out_of_range())?;
+        self.address(address)
+    }
+
+    fn wide_relative_address(&self, offset: &M::Word) -> Result<usize, IntcodeError> {
+        match offset.to_isize() {
+            Some(offset) => self.relative_address(offset),
+            // the sum may still fit
+            None => self.wide_address(
+                &M::Word::from_isize(self.relative_base)
+                    .checked_add(offset)
+                    .ok_or_else(|| self.fault_address_out_of_range())?,
+            ),
+        }
+    }
+
+    /// Adds `offset` to the relative base, faulting when the result doesn't
+    /// fit an `isize`.
+    fn adjust_relative_base(&mut self, offset: &M::Word) -> Result<(), IntcodeError> {
+        self.relative_base = match offset.to_isize() {
+            Some(offset) => self.relative_base.checked_add(offset),
+            None => M::Word::from_isize(self.relative_base)
+                .checked_add(offset)
+                .and_then(|base| base.to_isize()),
+        }
+        .ok_or_else(|| self.fault_overflow())?;
+        Ok(())
+    }
+
+    fn relative_address(&self, offset: isize) -> Result<usize, IntcodeError> {
+        let address = self
+            .relative_base
+            .checked_add(offset)
+            .ok_or_else(|| self.fault_overflow())?;
+        self.address(address)
+    }
+
+    fn faulting_instruction(&self) -> isize {
+        self.get_mem(self.idx).saturating_isize()
+    }
+
+    fn fault_overflow(&self) -> IntcodeError {
+        IntcodeError::ArithmeticOverflow {
+            ip: self.idx,
+            instruction: self.faulting_instruction(),
+        }
+    }
+
+    fn fault_address_out_of_range(&self) -> IntcodeError {
+        IntcodeError::AddressOutOfRange {
+            ip: self.idx,
+            instruction: self.faulting_instruction(),
+        }
+    }
+
+    fn fault_write_to_immediate(&self) -> IntcodeError {
+        IntcodeError::WriteToImmediate {
+            ip: self.idx,
+            instruction: self.faulting_instruction(),
+        }
+    }
+
+    /// Number of memory cells the program occupies (including cells written past its end).
+    pub fn mem_len(&self) -> usize {
+        self.mem.len()
+    }
+
+    pub fn get_mem(&self, idx: usize) -> M::Word {
+        self.mem.get(idx)
+    }
+
+    /// Cell at `idx` for writing from outside the program. Doesn't check the
+    /// memory limit, [`Self::try_get_mem_mut`] and [`Self::set_mem`] do.
+    pub fn get_mem_mut(&mut self, idx: usize) -> &mut M::Word {
+        if let Some(tracker) = &mut self.self_modification {
+            tracker.write(None, idx);
+        }
+        if let Some(cache) = &mut self.decode_cache {
+            cache.invalidate(idx);
+        }
+        self.mem.get_mut(idx)
+    }
+
+    /// Like [`Self::get_mem_mut`], but faults with
+    /// [`IntcodeError::MemoryLimit`] instead of growing the memory past the
+    /// limit.
+    pub fn try_get_mem_mut(&mut self, idx: usize) -> Result<&mut M::Word, IntcodeError> {
+        self.check_memory_limit(idx)?;
+        Ok(self.get_mem_mut(idx))
+    }
+
+    pub fn set_mem(&mut self, idx: usize, value: M::Word) -> Result<(), IntcodeError> {
+        *self.try_get_mem_mut(idx)? = value;
+        Ok(())
+    }
+
+    /// Executes a single instruction.
+    ///
+    /// `input` is consumed only when the instruction reads input.
+    pub fn step(
+        &mut self,
+        input: &mut Option<M::Word>,
+    ) -> Result<StepEvent<M::Word>, IntcodeError> {
+        if self.profile.is_none() {
+            return self.step_instruction(input);
+        }
+        let started = Instant::now();
+        let event = self.step_instruction(input);
+        let waiting = matches!(&event, Ok(event) if event.result == StepResult::WaitingForInput);
+        if let Some(profile) = &mut self.profile {
+            profile.stepped(started, waiting);
+        }
+        event
+    }
+
+    /// Bookkeeping of executing the instruction at `ip`.
+    fn executed(&mut self, ip: usize, opcode: Opcode) {
+        if let Some(tracker) = &mut self.self_modification {
+            tracker.execute(ip, 1 + opcode.arity());
+        }
+        if let Some(profile) = &mut self.profile {
+            profile.execute(ip, opcode);
+        }
+    }
+
+    fn step_instruction(
+        &mut self,
+        input: &mut Option<M::Word>,
+    ) -> Result<StepEvent<M::Word>, IntcodeError> {
+        let ip = self.idx;
+        let mut event = StepEvent {
+            ip,
+            instruction: Instruction::END_OF_MEMORY,
+            operands: [None, None, None],
+            write: None,
+            jump: None,
+            result: StepResult::Finished,
+        };
+        if ip >= self.mem.len() {
+            return Ok(event);
+        }
+
+        let instr = match self.decode_cache.as_ref().and_then(|cache| cache.get(ip)) {
+            Some(instr) => instr,
+            None => {
+                let word = self.mem.get(ip).saturating_isize();
+                let instr: Instruction = self
+                    .mem
+                    .get(ip)
+                    .to_isize()
+                    .ok_or(DecodeError::UnknownOpcode(word))
+                    .and_then(Instruction::try_from)
+                    .map_err(|err| IntcodeError::from_decode(ip, word, err))?;
+                if let Some(cache) = &mut self.decode_cache {
+                    cache.insert(ip, instr);
                 }
-                Opcode::AdjustRelativeBase => {
-                    self.relative_base += instr.arg1_mode.get(self, 1);
-                    self.idx += 2;
+                instr
+            }
+        };
+        event.instruction = instr;
+        let arity = instr.opcode.arity();
+        for (offset, (operand, mode)) in event
+            .operands
+            .iter_mut()
+            .zip(instr.modes())
+            .take(arity)
+            .enumerate()
+        {
+            *operand = Some(self.operand(mode, offset + 1)?);
+        }
+        // an input instruction without input runs again once it gets some
+        if instr.opcode != Opcode::Input || input.is_some() {
+            self.executed(ip, instr.opcode);
+        }
+        let [a, b, c] = event.operands.clone();
+
+        event.result = StepResult::Executed;
+        match instr.opcode {
+            Opcode::Add | Opcode::Mul | Opcode::LessThan | Opcode::Equals => {
+                let (a, b, c) = (a.unwrap(), b.unwrap(), c.unwrap());
+                let result = match instr.opcode {
+                    Opcode::Add => a.value.checked_add(&b.value),
+                    Opcode::Mul => a.value.checked_mul(&b.value),
+                    Opcode::LessThan => {
+                        Some(Word::from_isize(if a.value < b.value { 1 } else { 0 }))
+                    }
+                    _ => Some(Word::from_isize(if a.value == b.value { 1 } else { 0 })),
                 }
-                Opcode::Halt => {
-                    return Ok(RunResult::Finished);
+                .ok_or_else(|| self.fault_overflow())?;
+                event.write = Some(self.write(&c, result)?);
+            }
+            Opcode::Input => {
+                if let Some(value) = input.take() {
+                    event.write = Some(self.write(&a.unwrap(), value)?);
+                } else {
+                    event.result = StepResult::WaitingForInput;
+                    return Ok(event);
                 }
-                _ => {
-                    instr.execute(self);
+            }
+            Opcode::Output => {
+                event.result = StepResult::Output(a.unwrap().value);
+            }
+            Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+                let (a, b) = (a.unwrap(), b.unwrap());
+                let taken = a.value.is_zero() != (instr.opcode == Opcode::JumpIfTrue);
+                let target = b.value.saturating_isize();
+                event.jump = Some(Jump { target, taken });
+                if taken {
+                    self.idx = self.wide_address(&b.value)?;
+                    return Ok(event);
                 }
             }
+            Opcode::AdjustRelativeBase => {
+                self.adjust_relative_base(&a.unwrap().value)?;
+            }
+            Opcode::Halt => {
+                event.result = StepResult::Finished;
+                return Ok(event);
+            }
+        }
+        self.idx += 1 + arity;
+        Ok(event)
+    }
+
+    pub fn run(&mut self, mut input: Option<M::Word>) -> Result<RunResult<M::Word>, IntcodeError> {
+        loop {
+            match self.step(&mut input)?.result {
+                StepResult::Executed => {}
+                StepResult::WaitingForInput => return Ok(RunResult::WaitingForInput),
+                StepResult::Output(output) => return Ok(RunResult::Output(output)),
+                StepResult::Finished => return Ok(RunResult::Finished),
+            }
         }
-        Ok(RunResult::Finished)
     }
 
     pub fn run_with_constant_input(
         &mut self,
-        input: isize,
-    ) -> Result<Option<isize>, anyhow::Error> {
+        input: M::Word,
+    ) -> Result<Option<M::Word>, IntcodeError> {
+        loop {
+            match self.run(Some(input.clone()))? {
+                RunResult::Finished => return Ok(None),
+                RunResult::WaitingForInput => continue,
+                RunResult::Output(output) => return Ok(Some(output)),
+            }
+        }
+    }
+}
+
+/// Something that runs intcode programs: the interpreter ([`Computer`]) or
+/// the [compiled](compiled::Compiled) tier, with the same results.
+pub trait Engine {
+    type Memory: Memory;
+
+    /// Runs until the program halts, outputs a value or needs input it
+    /// wasn't given.
+    fn run(
+        &mut self,
+        input: Option<<Self::Memory as Memory>::Word>,
+    ) -> Result<RunResult<<Self::Memory as Memory>::Word>, IntcodeError>;
+
+    /// Executes a single instruction like [`Computer::step`], returns its
+    /// result and the address it wrote to.
+    #[allow(clippy::type_complexity)]
+    fn step(
+        &mut self,
+        input: &mut Option<<Self::Memory as Memory>::Word>,
+    ) -> Result<(StepResult<<Self::Memory as Memory>::Word>, Option<usize>), IntcodeError>;
+
+    fn computer(&self) -> &Computer<Self::Memory>;
+
+    /// Runs feeding `input` whenever the program asks, `None` once it halts.
+    fn run_with_constant_input(
+        &mut self,
+        input: <Self::Memory as Memory>::Word,
+    ) -> Result<Option<<Self::Memory as Memory>::Word>, IntcodeError> {
         loop {
-            match self.run(Some(input))? {
+            match self.run(Some(input.clone()))? {
                 RunResult::Finished => return Ok(None),
                 RunResult::WaitingForInput => continue,
                 RunResult::Output(output) => return Ok(Some(output)),
@@ -235,13 +841,57 @@ impl Computer {
     }
 }
 
+impl<M: Memory> Engine for Computer<M> {
+    type Memory = M;
+
+    fn run(&mut self, input: Option<M::Word>) -> Result<RunResult<M::Word>, IntcodeError> {
+        Computer::run(self, input)
+    }
+
+    fn step(
+        &mut self,
+        input: &mut Option<M::Word>,
+    ) -> Result<(StepResult<M::Word>, Option<usize>), IntcodeError> {
+        let event = Computer::step(self, input)?;
+        Ok((event.result, event.write.map(|write| write.address)))
+    }
+
+    fn computer(&self) -> &Computer<M> {
+        self
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use super::compiled::Compiled;
     use super::*;
 
+    /// Runs `f` on `computer` and on a compiled copy of it, checking that
+    /// both engines agree on the result and on the state they end up in.
+    fn on_both_engines<M, R, F>(computer: &mut Computer<M>, f: F) -> R
+    where
+        M: Memory + 'static,
+        M::Word: Send + Sync,
+        R: PartialEq + fmt::Debug,
+        F: Fn(&mut dyn Engine<Memory = M>) -> R,
+    {
+        let mut compiled = Compiled::new(computer.clone());
+        let expected = f(computer);
+        assert_eq!(f(&mut compiled), expected, "compiled engine disagrees");
+        let compiled = compiled.computer();
+        assert_eq!(
+            (compiled.ip(), compiled.relative_base(), compiled.mem_len()),
+            (computer.ip(), computer.relative_base(), computer.mem_len())
+        );
+        if computer.mem_len() <= 1 << 16 {
+            assert_eq!(compiled.mem.to_vec(), computer.mem.to_vec());
+        }
+        expected
+    }
+
     fn run_without_input(program: &str) -> Computer {
         let mut c: Computer = program.parse().unwrap();
-        if let Ok(RunResult::Finished) = c.run(None) {
+        if let Ok(RunResult::Finished) = on_both_engines(&mut c, |e| e.run(None)) {
             c
         } else {
             panic!("run_without_input didn't finish correctly")
@@ -264,7 +914,9 @@ mod tests {
 
     fn run(program: &str, input: isize) -> isize {
         let mut c: Computer = program.parse().unwrap();
-        c.run_with_constant_input(input).unwrap().unwrap()
+        on_both_engines(&mut c, |e| e.run_with_constant_input(input))
+            .unwrap()
+            .unwrap()
     }
 
     #[test]
@@ -309,14 +961,16 @@ mod tests {
         const PROGRAM: &str = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
         let mut c: Computer = PROGRAM.parse().unwrap();
         let parsed_program = c.mem.clone();
-        let mut output = Vec::new();
-        loop {
-            match c.run(None).unwrap() {
-                RunResult::Finished => break,
-                RunResult::WaitingForInput => panic!("shouldn't wait for input"),
-                RunResult::Output(out) => output.push(out),
+        let output = on_both_engines(&mut c, |e| {
+            let mut output = Vec::new();
+            loop {
+                match e.run(None).unwrap() {
+                    RunResult::Finished => break output,
+                    RunResult::WaitingForInput => panic!("shouldn't wait for input"),
+                    RunResult::Output(out) => output.push(out),
+                }
             }
-        }
+        });
         assert_eq!(parsed_program, output);
     }
 
@@ -328,4 +982,315 @@ mod tests {
         );
         assert_eq!(run("104,1125899906842624,99", 0), 1125899906842624);
     }
+
+    #[test]
+    fn test_faults() {
+        let fault = |program: &str| {
+            let mut c: Computer = program.parse().unwrap();
+            on_both_engines(&mut c, |e| e.run(None)).unwrap_err()
+        };
+        assert_eq!(
+            fault("1,0,0,0,42"),
+            IntcodeError::UnknownOpcode {
+                ip: 4,
+                instruction: 42
+            }
+        );
+        assert_eq!(
+            fault("301,0,0,0,99"),
+            IntcodeError::UnknownMode {
+                ip: 0,
+                instruction: 301
+            }
+        );
+        assert_eq!(
+            fault("11101,1,1,0,99"),
+            IntcodeError::WriteToImmediate {
+                ip: 0,
+                instruction: 11101
+            }
+        );
+        assert_eq!(
+            fault("1,-3,0,0,99"),
+            IntcodeError::NegativeAddress {
+                ip: 0,
+                instruction: 1,
+                address: -3
+            }
+        );
+        assert_eq!(
+            fault("1105,1,-1"),
+            IntcodeError::NegativeAddress {
+                ip: 0,
+                instruction: 1105,
+                address: -1
+            }
+        );
+        assert_eq!(
+            fault(&format!("1102,{},2,0,99", isize::MAX)),
+            IntcodeError::ArithmeticOverflow {
+                ip: 0,
+                instruction: 1102
+            }
+        );
+    }
+
+    #[test]
+    fn test_memory_backends() {
+        use memory::{DenseMemory, SparseMemory};
+
+        // stores 2 at address 10^12 and outputs it back
+        const PROGRAM: &str = "1101,1,1,1000000000000,4,1000000000000,99";
+        let mut sparse: Computer<SparseMemory> = PROGRAM.parse().unwrap();
+        assert_eq!(
+            on_both_engines(&mut sparse, |e| e.run(None)).unwrap(),
+            RunResult::Output(2)
+        );
+        assert_eq!(sparse.mem_len(), 1_000_000_000_001);
+
+        let fault = IntcodeError::MemoryLimit {
+            ip: 0,
+            instruction: 1101,
+            address: 1_000_000_000_000,
+        };
+        let dense: Computer<DenseMemory> = PROGRAM.parse().unwrap();
+        let mut dense = dense.with_memory_limit(1 << 20);
+        assert_eq!(on_both_engines(&mut dense, |e| e.run(None)), Err(fault));
+        let paged: Computer<PagedMemory> = PROGRAM.parse().unwrap();
+        let mut paged = paged.with_memory_limit(1 << 20);
+        assert_eq!(on_both_engines(&mut paged, |e| e.run(None)), Err(fault));
+        let limited: Computer<SparseMemory> = PROGRAM.parse().unwrap();
+        let mut limited = limited.with_memory_limit(7);
+        assert_eq!(on_both_engines(&mut limited, |e| e.run(None)), Err(fault));
+
+        // dense and paged memories are limited by default
+        let mut dense: Computer<DenseMemory> = PROGRAM.parse().unwrap();
+        assert_eq!(on_both_engines(&mut dense, |e| e.run(None)), Err(fault));
+        let mut paged: Computer = PROGRAM.parse().unwrap();
+        assert_eq!(on_both_engines(&mut paged, |e| e.run(None)), Err(fault));
+        let mut paged: Computer = "1101,1,1,1099511627776,99".parse().unwrap();
+        assert!(matches!(
+            on_both_engines(&mut paged, |e| e.run(None)),
+            Err(IntcodeError::MemoryLimit { .. })
+        ));
+        let mut near: Computer = format!("1101,1,1,{},4,{0},99", memory::DEFAULT_LIMIT - 1)
+            .parse()
+            .unwrap();
+        assert_eq!(near.run(None).unwrap(), RunResult::Output(2));
+
+        // so are writes from outside
+        let mut paged: Computer = PROGRAM.parse().unwrap();
+        assert_eq!(
+            paged.set_mem(1_000_000_000_000, 1),
+            Err(IntcodeError::MemoryLimit {
+                ip: 0,
+                instruction: 1101,
+                address: 1_000_000_000_000
+            })
+        );
+        assert!(paged.try_get_mem_mut(memory::DEFAULT_LIMIT).is_err());
+        assert_eq!(paged.mem_len(), 7);
+        *paged.try_get_mem_mut(1).unwrap() = 5;
+        assert_eq!(paged.get_mem(1), 5);
+    }
+
+    #[test]
+    fn test_word_types() {
+        // doubles i64::MAX and outputs the result
+        let program = format!("1102,{},2,7,4,7,99,0", i64::MAX);
+        let mut narrow: Computer<PagedMemory<i64>> = program.parse().unwrap();
+        assert_eq!(
+            on_both_engines(&mut narrow, |e| e.run(None)),
+            Err(IntcodeError::ArithmeticOverflow {
+                ip: 0,
+                instruction: 1102
+            })
+        );
+        let mut wide: Computer<PagedMemory<i128>> = program.parse().unwrap();
+        assert_eq!(
+            on_both_engines(&mut wide, |e| e.run(None)).unwrap(),
+            RunResult::Output(2 * i64::MAX as i128)
+        );
+
+        // words that don't fit an isize can't be instructions
+        let mut huge: Computer<PagedMemory<i128>> = format!("{}", i128::MAX).parse().unwrap();
+        assert_eq!(
+            on_both_engines(&mut huge, |e| e.run(None)),
+            Err(IntcodeError::UnknownOpcode {
+                ip: 0,
+                instruction: isize::MAX
+            })
+        );
+    }
+
+    #[test]
+    fn test_wide_addresses() {
+        use memory::SparseMemory;
+
+        let run = |program: String| {
+            let mut c: Computer<SparseMemory<i128>> = program.parse().unwrap();
+            let result = on_both_engines(&mut c, |e| e.run(None));
+            (result, c.relative_base())
+        };
+        let out_of_range =
+            |instruction| Err(IntcodeError::AddressOutOfRange { ip: 0, instruction });
+        // [2^70] and [2^71] would alias if clamped
+        assert_eq!(
+            run(format!("1101,1,1,{},4,{},99", 1i128 << 70, 1i128 << 71)).0,
+            out_of_range(1101)
+        );
+        assert_eq!(run(format!("4,{},99", 1i128 << 71)).0, out_of_range(4));
+        assert_eq!(run(format!("1105,1,{}", 1i128 << 70)).0, out_of_range(1105));
+        assert_eq!(
+            run(format!("1106,1,{},99", 1i128 << 70)).0,
+            Ok(RunResult::Finished)
+        );
+
+        // would leave the relative base at -1 if clamped
+        assert_eq!(
+            run(format!("109,{},109,{},99", 1i128 << 70, -(1i128 << 70))),
+            (
+                Err(IntcodeError::ArithmeticOverflow {
+                    ip: 0,
+                    instruction: 109
+                }),
+                0
+            )
+        );
+        // a wide relative offset is fine when the address fits
+        assert_eq!(
+            run(format!(
+                "109,{},204,{},99,0,0,42",
+                isize::MIN,
+                -(isize::MIN as i128) + 7
+            )),
+            (Ok(RunResult::Output(42)), isize::MIN)
+        );
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn test_bigint() {
+        use num_bigint::BigInt;
+
+        // squares its input three times
+        let mut c: Computer<PagedMemory<BigInt>> =
+            "3,0,2,0,0,0,2,0,0,0,2,0,0,0,4,0,99".parse().unwrap();
+        let input: BigInt = i128::MAX.into();
+        assert_eq!(
+            on_both_engines(&mut c, |e| e.run(Some(input.clone()))).unwrap(),
+            RunResult::Output(input.pow(8))
+        );
+
+        // addresses and relative base adjustments wider than isize fault
+        let huge = BigInt::from(1) << 70;
+        let mut c: Computer<memory::SparseMemory<BigInt>> =
+            format!("1101,1,1,{},4,{},99", huge, &huge << 1)
+                .parse()
+                .unwrap();
+        assert_eq!(
+            on_both_engines(&mut c, |e| e.run(None)),
+            Err(IntcodeError::AddressOutOfRange {
+                ip: 0,
+                instruction: 1101
+            })
+        );
+        let mut c: Computer<memory::SparseMemory<BigInt>> =
+            format!("109,{},109,-{0},99", huge).parse().unwrap();
+        assert_eq!(
+            on_both_engines(&mut c, |e| e.run(None)),
+            Err(IntcodeError::ArithmeticOverflow {
+                ip: 0,
+                instruction: 109
+            })
+        );
+        assert_eq!(c.relative_base(), 0);
+    }
+
+    #[test]
+    fn test_step() {
+        let mut c: Computer = "1002,4,3,4,33".parse().unwrap();
+        let event = c.step(&mut None).unwrap();
+        assert_eq!(event.ip, 0);
+        assert_eq!(event.instruction.opcode(), Opcode::Mul);
+        assert_eq!(
+            event.instruction.modes(),
+            [Mode::Position, Mode::Immediate, Mode::Position]
+        );
+        assert_eq!(
+            event.operands,
+            [
+                Some(Operand {
+                    mode: Mode::Position,
+                    raw: 4,
+                    address: Some(4),
+                    value: 33
+                }),
+                Some(Operand {
+                    mode: Mode::Immediate,
+                    raw: 3,
+                    address: None,
+                    value: 3
+                }),
+                Some(Operand {
+                    mode: Mode::Position,
+                    raw: 4,
+                    address: Some(4),
+                    value: 33
+                }),
+            ]
+        );
+        assert_eq!(
+            event.write,
+            Some(MemoryWrite {
+                address: 4,
+                old: 33,
+                new: 99
+            })
+        );
+        assert_eq!(event.jump, None);
+        assert_eq!(event.result, StepResult::Executed);
+        assert_eq!(c.ip(), 4);
+        assert_eq!(c.step(&mut None).unwrap().result, StepResult::Finished);
+        assert_eq!(c.ip(), 4);
+    }
+
+    #[test]
+    fn test_step_jumps_and_relative_base() {
+        let mut c: Computer = "109,-2,1105,0,9,1106,0,9,0,3,7,99".parse().unwrap();
+        c.step(&mut None).unwrap();
+        assert_eq!(c.relative_base(), -2);
+        let event = c.step(&mut None).unwrap();
+        assert_eq!(
+            event.jump,
+            Some(Jump {
+                target: 9,
+                taken: false
+            })
+        );
+        assert_eq!(c.ip(), 5);
+        let event = c.step(&mut None).unwrap();
+        assert_eq!(
+            event.jump,
+            Some(Jump {
+                target: 9,
+                taken: true
+            })
+        );
+        assert_eq!(c.ip(), 9);
+
+        let mut input = None;
+        let event = c.step(&mut input).unwrap();
+        assert_eq!(event.result, StepResult::WaitingForInput);
+        assert_eq!(c.ip(), 9);
+        input = Some(5);
+        let event = c.step(&mut input).unwrap();
+        assert_eq!(input, None);
+        assert_eq!(
+            event.operands[0].map(|operand| operand.address),
+            Some(Some(7))
+        );
+        assert_eq!(c.get_mem(7), 5);
+        assert_eq!(c.step(&mut None).unwrap().result, StepResult::Finished);
+    }
 }
@@ -0,0 +1,195 @@
+//! Phase setting search for chains of amplifiers (Day 07): every
+//! permutation of the phase settings is wired into a [`Network`] and run,
+//! with permutations spread over worker threads.
+//!
+//! Amplifiers are run up to reading their phase setting once, every
+//! permutation starts from clones of those. An amplifier has to be waiting
+//! for its input signal by then.
+
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use aoc_helpers::permutations::permutations;
+
+use super::memory::Memory;
+use super::network::{Network, NetworkError, Scheduler};
+use super::{Computer, RunResult};
+
+/// How one permutation of phase settings went.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Permutation {
+    pub phases: Vec<isize>,
+    /// Last output of the last amplifier, if it output anything.
+    pub signal: Option<isize>,
+    /// Values passed between (and out of) the amplifiers.
+    pub transfers: usize,
+    pub elapsed: Duration,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PhaseSearch {
+    /// Every permutation, in the order they were generated.
+    pub permutations: Vec<Permutation>,
+}
+
+impl PhaseSearch {
+    /// Permutation with the highest signal.
+    pub fn best(&self) -> Option<&Permutation> {
+        self.permutations
+            .iter()
+            .filter(|permutation| permutation.signal.is_some())
+            .max_by_key(|permutation| permutation.signal)
+    }
+}
+
+/// Tries every order of `phases`, feeding 0 to the first amplifier and,
+/// with `feedback`, the output of the last one back to the first. Without
+/// phases there is nothing to try.
+pub fn search<M>(
+    program: &Computer<M>,
+    phases: &[isize],
+    feedback: bool,
+) -> Result<PhaseSearch, NetworkError>
+where
+    M: Memory<Word = isize> + Send + Sync,
+{
+    if phases.is_empty() {
+        return Ok(PhaseSearch::default());
+    }
+    let amplifiers = phases
+        .iter()
+        .enumerate()
+        .map(|(node, phase)| {
+            let mut amplifier = program.clone();
+            match amplifier.run(Some(*phase)) {
+                Ok(RunResult::WaitingForInput) => Ok((*phase, amplifier)),
+                Ok(_) => Err(NetworkError::NotWaiting { node }),
+                Err(fault) => Err(NetworkError::Fault { node, fault }),
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut orders = Vec::new();
+    permutations((0..amplifiers.len()).collect(), |order| {
+        orders.push(order.to_vec())
+    });
+
+    let next = AtomicUsize::new(0);
+    let workers = thread::available_parallelism()
+        .map_or(1, NonZeroUsize::get)
+        .min(orders.len());
+    let mut results: Vec<(usize, Result<Permutation, NetworkError>)> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    loop {
+                        let idx = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(order) = orders.get(idx) else {
+                            break results;
+                        };
+                        results.push((idx, run_chain(&amplifiers, order, feedback)));
+                    }
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("worker shouldn't panic"))
+            .collect()
+    });
+    results.sort_by_key(|(idx, _)| *idx);
+
+    let permutations = results
+        .into_iter()
+        .map(|(_, permutation)| permutation)
+        .collect::<Result<_, _>>()?;
+    Ok(PhaseSearch { permutations })
+}
+
+fn run_chain<M: Memory<Word = isize>>(
+    amplifiers: &[(isize, Computer<M>)],
+    order: &[usize],
+    feedback: bool,
+) -> Result<Permutation, NetworkError> {
+    let start = Instant::now();
+    let mut network = Network::new();
+    let nodes: Vec<_> = order
+        .iter()
+        .map(|amplifier| network.add_node(amplifiers[*amplifier].1.clone(), []))
+        .collect();
+    for pair in nodes.windows(2) {
+        network.connect(pair[0], pair[1])?;
+    }
+    let last = nodes[nodes.len() - 1];
+    let scheduler = if feedback {
+        network.connect(last, nodes[0])?;
+        Scheduler::RoundRobin
+    } else {
+        Scheduler::Serial
+    };
+    network.send(nodes[0], 0);
+    network.run(scheduler)?;
+
+    Ok(Permutation {
+        phases: order
+            .iter()
+            .map(|amplifier| amplifiers[*amplifier].0)
+            .collect(),
+        signal: network.outputs(last).last().copied(),
+        transfers: nodes.iter().map(|node| network.outputs(*node).len()).sum(),
+        elapsed: start.elapsed(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search() {
+        let program: Computer = "3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0"
+            .parse()
+            .unwrap();
+        let result = search(&program, &[0, 1, 2, 3, 4], false).unwrap();
+        assert_eq!(result.permutations.len(), 120);
+        let best = result.best().unwrap();
+        assert_eq!(best.phases, vec![4, 3, 2, 1, 0]);
+        assert_eq!(best.signal, Some(43210));
+        assert_eq!(best.transfers, 5);
+
+        let program: Computer =
+            "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5"
+                .parse()
+                .unwrap();
+        let result = search(&program, &[5, 6, 7, 8, 9], true).unwrap();
+        let best = result.best().unwrap();
+        assert_eq!(best.phases, vec![9, 8, 7, 6, 5]);
+        assert_eq!(best.signal, Some(139629729));
+    }
+
+    #[test]
+    fn test_errors() {
+        let program: Computer = "3,0,42".parse().unwrap();
+        assert!(matches!(
+            search(&program, &[0, 1], false),
+            Err(NetworkError::Fault { node: 0, .. })
+        ));
+        assert_eq!(search(&program, &[], false), Ok(PhaseSearch::default()));
+
+        // outputs before reading the input signal
+        let program: Computer = "3,0,104,1,3,0,99".parse().unwrap();
+        assert_eq!(
+            search(&program, &[0, 1], false),
+            Err(NetworkError::NotWaiting { node: 0 })
+        );
+        // halts right after reading its phase setting
+        let program: Computer = "3,0,99".parse().unwrap();
+        assert_eq!(
+            search(&program, &[0, 1], true),
+            Err(NetworkError::NotWaiting { node: 0 })
+        );
+    }
+}
@@ -0,0 +1,390 @@
+//! Control-flow graph of a program, built on top of the [disassembler](super::disasm).
+//!
+//! Code is split into basic blocks at jump targets and after jumps. Jumps
+//! with immediate targets become edges, the rest are marked as indirect.
+//! Subroutines follow the calling convention of the compiled puzzle
+//! programs: the caller stores the return address (usually relative to the
+//! relative base) and jumps to the subroutine, which returns with an
+//! indirect jump through that stored address.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt::Write;
+
+use super::disasm::{disassemble, stored_constant, Line, Listing};
+use super::memory::Memory;
+use super::{Computer, Mode, Opcode};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EdgeKind {
+    Fallthrough,
+    Jump,
+    Call,
+    /// From a call to where the subroutine returns.
+    Return,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Edge {
+    pub target: usize,
+    pub kind: EdgeKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Block {
+    pub start: usize,
+    /// Address right behind the last instruction.
+    pub end: usize,
+    pub lines: Vec<Line>,
+    pub edges: Vec<Edge>,
+    /// Ends with a jump whose target is only known at runtime.
+    pub indirect: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Subroutine {
+    pub entry: usize,
+    /// Addresses of the jumps calling it.
+    pub call_sites: BTreeSet<usize>,
+    /// Starts of the blocks reachable from the entry without calls.
+    pub blocks: BTreeSet<usize>,
+}
+
+/// Instruction writing into code with an immediate address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CodeWrite {
+    pub address: usize,
+    pub target: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Cfg {
+    /// Blocks by their start.
+    pub blocks: BTreeMap<usize, Block>,
+    pub subroutines: Vec<Subroutine>,
+    pub code_writes: Vec<CodeWrite>,
+}
+
+pub fn analyze<M: Memory<Word = isize>>(computer: &Computer<M>) -> Cfg {
+    Cfg::from(&disassemble(computer))
+}
+
+/// Jump with an immediate target that is always taken.
+fn is_unconditional_jump(line: &Line) -> bool {
+    let Line::Code {
+        instruction,
+        params,
+        ..
+    } = line
+    else {
+        return false;
+    };
+    let always = match instruction.opcode() {
+        Opcode::JumpIfTrue => params[0] != 0,
+        Opcode::JumpIfFalse => params[0] == 0,
+        _ => false,
+    };
+    always && instruction.modes()[..2] == [Mode::Immediate, Mode::Immediate]
+}
+
+impl From<&Listing> for Cfg {
+    fn from(listing: &Listing) -> Self {
+        let code: Vec<&Line> = listing
+            .lines
+            .iter()
+            .filter(|line| matches!(line, Line::Code { .. }))
+            .collect();
+        let is_code = |address: usize| match listing.line_at(address) {
+            Some(line @ Line::Code { .. }) => line.address() == address,
+            _ => false,
+        };
+        let return_addresses: BTreeSet<isize> = code
+            .iter()
+            .filter_map(|line| match line {
+                Line::Code {
+                    instruction,
+                    params,
+                    ..
+                } => stored_constant(instruction, params),
+                Line::Data { .. } => None,
+            })
+            .collect();
+
+        let mut leaders: BTreeSet<usize> = listing.jump_targets.clone();
+        let mut previous_end = None;
+        for line in &code {
+            let Line::Code {
+                address,
+                instruction,
+                ..
+            } = line
+            else {
+                continue;
+            };
+            if previous_end != Some(*address) {
+                leaders.insert(*address);
+            }
+            let end = address + line.size();
+            if matches!(
+                instruction.opcode(),
+                Opcode::JumpIfTrue | Opcode::JumpIfFalse | Opcode::Halt
+            ) {
+                leaders.insert(end);
+            }
+            previous_end = Some(end);
+        }
+
+        let mut blocks: BTreeMap<usize, Block> = BTreeMap::new();
+        let mut code_writes = Vec::new();
+        for line in code {
+            let Line::Code {
+                address,
+                instruction,
+                params,
+            } = *line
+            else {
+                continue;
+            };
+            if leaders.contains(&address) {
+                blocks.insert(
+                    address,
+                    Block {
+                        start: address,
+                        end: address,
+                        lines: Vec::new(),
+                        edges: Vec::new(),
+                        indirect: false,
+                    },
+                );
+            }
+            let block = blocks
+                .values_mut()
+                .next_back()
+                .expect("first code line is a leader");
+            block.lines.push(*line);
+            block.end = address + line.size();
+
+            if let Some(idx) = instruction.opcode().written_param() {
+                if instruction.modes()[idx] == Mode::Position {
+                    if let Ok(target) = usize::try_from(params[idx]) {
+                        if let Some(Line::Code { .. }) = listing.line_at(target) {
+                            code_writes.push(CodeWrite { address, target });
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut calls: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+        for block in blocks.values_mut() {
+            let last = *block.lines.last().expect("blocks aren't empty");
+            let Line::Code {
+                address,
+                instruction,
+                params,
+            } = last
+            else {
+                continue;
+            };
+            let [cond_mode, target_mode, _] = instruction.modes();
+            let (falls_through, jumps) = match instruction.opcode() {
+                Opcode::Halt => (false, false),
+                Opcode::JumpIfTrue | Opcode::JumpIfFalse if cond_mode == Mode::Immediate => {
+                    let taken = (params[0] != 0) == (instruction.opcode() == Opcode::JumpIfTrue);
+                    (!taken, taken)
+                }
+                Opcode::JumpIfTrue | Opcode::JumpIfFalse => (true, true),
+                _ => (true, false),
+            };
+            let is_call = is_unconditional_jump(&last)
+                && return_addresses.contains(&(block.end as isize))
+                && is_code(block.end);
+
+            if jumps && target_mode != Mode::Immediate {
+                block.indirect = true;
+            } else if jumps {
+                if let Some(target) = usize::try_from(params[1]).ok().filter(|t| is_code(*t)) {
+                    let kind = if is_call {
+                        calls.entry(target).or_default().insert(address);
+                        EdgeKind::Call
+                    } else {
+                        EdgeKind::Jump
+                    };
+                    block.edges.push(Edge { target, kind });
+                }
+            }
+            if is_call {
+                block.edges.push(Edge {
+                    target: block.end,
+                    kind: EdgeKind::Return,
+                });
+            } else if falls_through && is_code(block.end) {
+                block.edges.push(Edge {
+                    target: block.end,
+                    kind: EdgeKind::Fallthrough,
+                });
+            }
+        }
+
+        let subroutines = calls
+            .into_iter()
+            .map(|(entry, call_sites)| {
+                let mut reached = BTreeSet::from([entry]);
+                let mut pending = VecDeque::from([entry]);
+                while let Some(start) = pending.pop_front() {
+                    for edge in &blocks[&start].edges {
+                        if edge.kind != EdgeKind::Call && reached.insert(edge.target) {
+                            pending.push_back(edge.target);
+                        }
+                    }
+                }
+                Subroutine {
+                    entry,
+                    call_sites,
+                    blocks: reached,
+                }
+            })
+            .collect();
+
+        Self {
+            blocks,
+            subroutines,
+            code_writes,
+        }
+    }
+}
+
+impl Cfg {
+    pub fn block_at(&self, address: usize) -> Option<&Block> {
+        self.blocks
+            .range(..=address)
+            .next_back()
+            .map(|(_, block)| block)
+            .filter(|block| address < block.end)
+    }
+
+    /// Graphviz rendering: blocks with their listings, subroutine entries
+    /// doubly outlined, indirect jumps dashed and writes into code in red.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph cfg {\n    node [shape=box, fontname=monospace];\n");
+        let entries: BTreeSet<usize> = self.subroutines.iter().map(|sub| sub.entry).collect();
+        for block in self.blocks.values() {
+            let label: String = block
+                .lines
+                .iter()
+                .map(|line| format!("{}\\l", line.to_string().replace('"', "\\\"")))
+                .collect();
+            let peripheries = if entries.contains(&block.start) { 2 } else { 1 };
+            writeln!(
+                dot,
+                "    b{} [label=\"{}\", peripheries={}];",
+                block.start, label, peripheries
+            )
+            .unwrap();
+            for edge in &block.edges {
+                let style = match edge.kind {
+                    EdgeKind::Fallthrough => "",
+                    EdgeKind::Jump => " [label=\"jump\"]",
+                    EdgeKind::Call => " [label=\"call\", style=bold]",
+                    EdgeKind::Return => " [label=\"return\", style=dotted]",
+                };
+                writeln!(dot, "    b{} -> b{}{};", block.start, edge.target, style).unwrap();
+            }
+            if block.indirect {
+                writeln!(dot, "    b{} -> indirect [style=dashed];", block.start).unwrap();
+            }
+        }
+        if self.blocks.values().any(|block| block.indirect) {
+            dot.push_str("    indirect [label=\"?\", shape=circle];\n");
+        }
+        for write in &self.code_writes {
+            if let (Some(from), Some(to)) =
+                (self.block_at(write.address), self.block_at(write.target))
+            {
+                writeln!(
+                    dot,
+                    "    b{} -> b{} [label=\"writes {}\", color=red, style=dashed];",
+                    from.start, to.start, write.target
+                )
+                .unwrap();
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::asm::assemble;
+    use super::*;
+
+    fn edges(cfg: &Cfg, start: usize) -> Vec<(usize, EdgeKind)> {
+        cfg.blocks[&start]
+            .edges
+            .iter()
+            .map(|edge| (edge.target, edge.kind))
+            .collect()
+    }
+
+    #[test]
+    fn test_blocks_and_code_writes() {
+        let computer = assemble(
+            "
+                    in -> [x]
+                    jf [x], #end
+                    mov #104 -> [patch]
+            patch:  out #5
+            end:    hlt
+            x:      .data 0
+            ",
+        )
+        .unwrap();
+        let cfg = analyze(&computer);
+        assert_eq!(
+            cfg.blocks.keys().copied().collect::<Vec<_>>(),
+            vec![0, 5, 11]
+        );
+        assert_eq!(
+            edges(&cfg, 0),
+            vec![(11, EdgeKind::Jump), (5, EdgeKind::Fallthrough)]
+        );
+        assert_eq!(edges(&cfg, 5), vec![(11, EdgeKind::Fallthrough)]);
+        assert_eq!(edges(&cfg, 11), vec![]);
+        assert_eq!(
+            cfg.code_writes,
+            vec![CodeWrite {
+                address: 5,
+                target: 9
+            }]
+        );
+        assert_eq!(cfg.block_at(10).map(|block| block.start), Some(5));
+        assert_eq!(cfg.block_at(12), None);
+
+        let dot = cfg.to_dot();
+        assert!(dot.starts_with("digraph cfg {"));
+        assert!(dot.contains("b0 -> b11 [label=\"jump\"];"));
+        assert!(dot.contains("b5 -> b5 [label=\"writes 9\", color=red, style=dashed];"));
+    }
+
+    #[test]
+    fn test_subroutines() {
+        // stores return address 7 into [rb+0], jumps to the subroutine at 8
+        // which jumps back through [rb+0]
+        let computer: Computer = "21101,7,0,0,1105,1,8,99,2105,1,0".parse().unwrap();
+        let cfg = analyze(&computer);
+        assert_eq!(
+            edges(&cfg, 0),
+            vec![(8, EdgeKind::Call), (7, EdgeKind::Return)]
+        );
+        assert!(cfg.blocks[&8].indirect);
+        assert_eq!(
+            cfg.subroutines,
+            vec![Subroutine {
+                entry: 8,
+                call_sites: BTreeSet::from([4]),
+                blocks: BTreeSet::from([8]),
+            }]
+        );
+        assert!(cfg.to_dot().contains("b8 -> indirect [style=dashed];"));
+    }
+}
@@ -0,0 +1,129 @@
+//! Text interface for programs that talk ASCII: input is sent as lines of
+//! character codes, printed characters are collected into lines and anything
+//! outside of ASCII (usually the answer) is reported as a plain value.
+
+use std::collections::VecDeque;
+
+use super::io::{RunError, ToFn};
+use super::memory::{Memory, PagedMemory};
+use super::{Computer, IntcodeError, RunResult};
+
+/// Everything a program printed during one [`Ascii::run`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Transcript {
+    /// Printed lines without their newlines; the last one may be unfinished
+    /// (like a prompt waiting for an answer).
+    pub lines: Vec<String>,
+    /// Outputs that are not ASCII characters.
+    pub values: Vec<isize>,
+    pub finished: bool,
+}
+
+impl Transcript {
+    pub fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Ascii<M = PagedMemory> {
+    computer: Computer<M>,
+    input: VecDeque<isize>,
+}
+
+impl<M: Memory<Word = isize>> Ascii<M> {
+    pub fn new(computer: Computer<M>) -> Self {
+        Self {
+            computer,
+            input: Default::default(),
+        }
+    }
+
+    pub fn computer(&self) -> &Computer<M> {
+        &self.computer
+    }
+
+    pub fn into_inner(self) -> Computer<M> {
+        self.computer
+    }
+
+    /// Queues the character codes of `text` as it is.
+    pub fn send(&mut self, text: &str) {
+        self.input.extend(text.bytes().map(isize::from));
+    }
+
+    /// Queues `line` followed by a newline.
+    pub fn send_line(&mut self, line: &str) {
+        self.send(line);
+        self.input.push_back(isize::from(b'\n'));
+    }
+
+    /// Runs until the program halts or needs more input than is queued.
+    pub fn run(&mut self) -> Result<Transcript, IntcodeError> {
+        let mut transcript = Transcript::default();
+        let mut line = String::new();
+        let result = self
+            .computer
+            .run_with_io(
+                &mut self.input,
+                ToFn(
+                    |value: isize| match u8::try_from(value).ok().filter(u8::is_ascii) {
+                        Some(b'\n') => transcript.lines.push(std::mem::take(&mut line)),
+                        Some(c) => line.push(char::from(c)),
+                        None => transcript.values.push(value),
+                    },
+                ),
+            )
+            .map_err(RunError::into_fault)?;
+        transcript.finished = result == RunResult::Finished;
+        if !line.is_empty() {
+            transcript.lines.push(line);
+        }
+        Ok(transcript)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::asm::assemble;
+    use super::*;
+
+    // prompts for a line, echoes it back and reports its length plus 1000
+    const ECHO: &str = "
+                out #62
+                out #32
+        loop:   in -> [c]
+                eq [c], #10 -> [t]
+                jt [t], #done
+                out [c]
+                add [n], #1 -> [n]
+                jmp #loop
+        done:   out #10
+                add [n], #1000 -> [n]
+                out [n]
+                hlt
+        c:      .data 0
+        t:      .data 0
+        n:      .data 0
+    ";
+
+    #[test]
+    fn test_dialog() {
+        let mut echo = Ascii::new(assemble(ECHO).unwrap());
+        assert_eq!(
+            echo.run().unwrap(),
+            Transcript {
+                lines: vec!["> ".to_owned()],
+                values: vec![],
+                finished: false,
+            }
+        );
+
+        echo.send("Hello");
+        echo.send_line(", world!");
+        let transcript = echo.run().unwrap();
+        assert_eq!(transcript.text(), "Hello, world!");
+        assert_eq!(transcript.values, vec![1013]);
+        assert!(transcript.finished);
+    }
+}
@@ -0,0 +1,593 @@
+//! Assembler for intcode programs.
+//!
+//! One statement per line, `;` starts a comment:
+//!
+//! ```text
+//! start:  arb #stack          ; labels end with a colon
+//!         in -> [value]       ; destination after `->` (or as the last operand)
+//!         mul [value], #2 -> [rb+0]
+//!         out [rb+0]
+//!         jt #1, #start
+//! value:  .data 0, "text\n", start
+//! stack:
+//! ```
+//!
+//! Instructions use the [`Opcode`] mnemonics (`add`, `mul`, `in`, `out`, `jt`,
+//! `jf`, `lt`, `eq`, `arb`, `hlt`) and the parameter modes are given by sigils:
+//! `[addr]` for [`Mode::Position`], `#value` for [`Mode::Immediate`] and
+//! `[rb+offset]` for [`Mode::Relative`]. Addresses and values may be numbers,
+//! labels or `label+offset`. A purely numeric label (`0042:`, as printed by
+//! the [disassembler](super::disasm)) asserts the current address instead of
+//! defining a label.
+//!
+//! Helpers built on the relative base, which is used as a stack pointer to
+//! the next free cell:
+//!
+//! - `mov src -> dst` copies a value,
+//! - `jmp target` jumps unconditionally,
+//! - `push src` and `pop -> dst` (relative destinations see the popped base),
+//! - `call target` pushes the return address and jumps, `ret` pops it and
+//!   jumps back.
+
+use std::collections::HashMap;
+
+use aoc_helpers::anyhow;
+
+use super::{Computer, Instruction, Mode, Opcode};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Expr {
+    label: Option<String>,
+    offset: isize,
+}
+
+impl Expr {
+    fn number(offset: isize) -> Self {
+        Self {
+            label: None,
+            offset,
+        }
+    }
+
+    fn label(label: String) -> Self {
+        Self {
+            label: Some(label),
+            offset: 0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Param {
+    mode: Mode,
+    expr: Expr,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Item {
+    Label(String),
+    Address(usize),
+    Instruction(Opcode, Vec<Param>),
+    Data(Vec<Expr>),
+}
+
+impl Item {
+    fn size(&self) -> usize {
+        match self {
+            Self::Label(_) | Self::Address(_) => 0,
+            Self::Instruction(opcode, _) => 1 + opcode.arity(),
+            Self::Data(values) => values.len(),
+        }
+    }
+}
+
+fn is_label(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '.')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn parse_number(s: &str) -> Result<isize, anyhow::Error> {
+    s.parse()
+        .map_err(|err| anyhow::anyhow!("Parsing {:?} to int failed: {}", s, err))
+}
+
+fn parse_expr(s: &str) -> Result<Expr, anyhow::Error> {
+    let s = s.trim();
+    if s.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '+') {
+        return parse_number(s).map(Expr::number);
+    }
+    let (label, offset) = match s.find(['+', '-']) {
+        Some(idx) => (
+            s[..idx].trim(),
+            parse_number(s[idx..].trim_start_matches('+'))?,
+        ),
+        None => (s, 0),
+    };
+    if !is_label(label) {
+        return Err(anyhow::anyhow!("Invalid label: {:?}", label));
+    }
+    Ok(Expr {
+        label: Some(label.to_owned()),
+        offset,
+    })
+}
+
+fn parse_param(s: &str) -> Result<Param, anyhow::Error> {
+    let s = s.trim();
+    if let Some(value) = s.strip_prefix('#') {
+        return Ok(Param {
+            mode: Mode::Immediate,
+            expr: parse_expr(value)?,
+        });
+    }
+    let inner = s
+        .strip_prefix('[')
+        .and_then(|s| s.strip_suffix(']'))
+        .ok_or_else(|| anyhow::anyhow!("Missing addressing mode in {:?}", s))?
+        .trim();
+    match inner.strip_prefix("rb") {
+        Some(offset) if !offset.starts_with(|c: char| c.is_ascii_alphanumeric()) => {
+            let offset = offset.trim();
+            Ok(Param {
+                mode: Mode::Relative,
+                expr: if offset.is_empty() {
+                    Expr::number(0)
+                } else if offset.starts_with('-') {
+                    parse_expr(offset)?
+                } else {
+                    parse_expr(
+                        offset
+                            .strip_prefix('+')
+                            .ok_or_else(|| anyhow::anyhow!("Invalid offset in {:?}", s))?,
+                    )?
+                },
+            })
+        }
+        _ => Ok(Param {
+            mode: Mode::Position,
+            expr: parse_expr(inner)?,
+        }),
+    }
+}
+
+/// Splits on commas that are not inside a string literal.
+fn split_list(s: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut start = 0;
+    for (idx, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ',' if !in_string => {
+                parts.push(&s[start..idx]);
+                start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (idx, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &line[..idx],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_string(s: &str) -> Result<Vec<Expr>, anyhow::Error> {
+    let inner = s
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .ok_or_else(|| anyhow::anyhow!("Unterminated string: {}", s))?;
+    let mut values = Vec::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        let c = if c == '\\' {
+            match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('0') => '\0',
+                Some(c @ ('\\' | '"')) => c,
+                other => return Err(anyhow::anyhow!("Invalid escape: \\{:?}", other)),
+            }
+        } else {
+            c
+        };
+        values.push(Expr::number(c as isize));
+    }
+    Ok(values)
+}
+
+fn parse_data(args: &str) -> Result<Item, anyhow::Error> {
+    let mut values = Vec::new();
+    for arg in split_list(args) {
+        let arg = arg.trim();
+        if arg.starts_with('"') {
+            values.extend(parse_string(arg)?);
+        } else {
+            values.push(parse_expr(arg)?);
+        }
+    }
+    Ok(Item::Data(values))
+}
+
+/// Parses `a, b -> c` (or `a, b, c`) into parameters.
+fn parse_params(args: &str) -> Result<Vec<Param>, anyhow::Error> {
+    let (sources, destination) = match args.split_once("->") {
+        Some((sources, destination)) => (sources, Some(destination)),
+        None => (args, None),
+    };
+    let mut params = Vec::new();
+    if !sources.trim().is_empty() {
+        for source in split_list(sources) {
+            params.push(parse_param(source)?);
+        }
+    }
+    if let Some(destination) = destination {
+        params.push(parse_param(destination)?);
+    }
+    Ok(params)
+}
+
+fn single_param(mnemonic: &str, args: &str) -> Result<Param, anyhow::Error> {
+    let mut params = parse_params(args)?;
+    if params.len() != 1 {
+        return Err(anyhow::anyhow!("{} takes 1 operand", mnemonic));
+    }
+    Ok(params.remove(0))
+}
+
+fn immediate(value: isize) -> Param {
+    Param {
+        mode: Mode::Immediate,
+        expr: Expr::number(value),
+    }
+}
+
+fn stack_top() -> Param {
+    Param {
+        mode: Mode::Relative,
+        expr: Expr::number(0),
+    }
+}
+
+fn parse_statement(
+    mnemonic: &str,
+    args: &str,
+    return_labels: &mut usize,
+) -> Result<Vec<Item>, anyhow::Error> {
+    if mnemonic.eq_ignore_ascii_case(".data") {
+        return Ok(vec![parse_data(args)?]);
+    }
+    if let Some(opcode) = Opcode::from_mnemonic(mnemonic) {
+        let params = parse_params(args)?;
+        if params.len() != opcode.arity() {
+            return Err(anyhow::anyhow!(
+                "{} takes {} operand(s), got {}",
+                opcode.mnemonic(),
+                opcode.arity(),
+                params.len()
+            ));
+        }
+        return Ok(vec![Item::Instruction(opcode, params)]);
+    }
+    Ok(match mnemonic.to_ascii_lowercase().as_str() {
+        "mov" => {
+            let params = parse_params(args)?;
+            if params.len() != 2 {
+                return Err(anyhow::anyhow!("mov takes 2 operands"));
+            }
+            let mut params = params.into_iter();
+            let (source, destination) = (params.next().unwrap(), params.next().unwrap());
+            vec![Item::Instruction(
+                Opcode::Add,
+                vec![source, immediate(0), destination],
+            )]
+        }
+        "jmp" => vec![Item::Instruction(
+            Opcode::JumpIfTrue,
+            vec![immediate(1), single_param(mnemonic, args)?],
+        )],
+        "push" => vec![
+            Item::Instruction(
+                Opcode::Add,
+                vec![single_param(mnemonic, args)?, immediate(0), stack_top()],
+            ),
+            Item::Instruction(Opcode::AdjustRelativeBase, vec![immediate(1)]),
+        ],
+        "pop" => vec![
+            Item::Instruction(Opcode::AdjustRelativeBase, vec![immediate(-1)]),
+            Item::Instruction(
+                Opcode::Add,
+                vec![stack_top(), immediate(0), single_param(mnemonic, args)?],
+            ),
+        ],
+        "call" => {
+            let label = format!(".ret{}", return_labels);
+            *return_labels += 1;
+            vec![
+                Item::Instruction(
+                    Opcode::Add,
+                    vec![
+                        Param {
+                            mode: Mode::Immediate,
+                            expr: Expr::label(label.clone()),
+                        },
+                        immediate(0),
+                        stack_top(),
+                    ],
+                ),
+                Item::Instruction(Opcode::AdjustRelativeBase, vec![immediate(1)]),
+                Item::Instruction(
+                    Opcode::JumpIfTrue,
+                    vec![immediate(1), single_param(mnemonic, args)?],
+                ),
+                Item::Label(label),
+            ]
+        }
+        "ret" if args.trim().is_empty() => vec![
+            Item::Instruction(Opcode::AdjustRelativeBase, vec![immediate(-1)]),
+            Item::Instruction(Opcode::JumpIfTrue, vec![immediate(1), stack_top()]),
+        ],
+        _ => return Err(anyhow::anyhow!("Unknown instruction: {:?}", mnemonic)),
+    })
+}
+
+fn parse_line(line: &str, return_labels: &mut usize) -> Result<Vec<Item>, anyhow::Error> {
+    let mut items = Vec::new();
+    let mut rest = strip_comment(line).trim();
+    while let Some((name, tail)) = rest.split_once(':') {
+        let name = name.trim();
+        if name.chars().all(|c| c.is_ascii_digit()) && !name.is_empty() {
+            items.push(Item::Address(name.parse()?));
+        } else if is_label(name) && !name.starts_with('.') {
+            items.push(Item::Label(name.to_owned()));
+        } else {
+            break;
+        }
+        rest = tail.trim();
+    }
+    if !rest.is_empty() {
+        let (mnemonic, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        items.extend(parse_statement(mnemonic, args, return_labels)?);
+    }
+    Ok(items)
+}
+
+fn resolve(expr: &Expr, labels: &HashMap<String, usize>) -> Result<isize, anyhow::Error> {
+    match &expr.label {
+        Some(label) => labels
+            .get(label)
+            .map(|address| *address as isize + expr.offset)
+            .ok_or_else(|| anyhow::anyhow!("Unknown label: {:?}", label)),
+        None => Ok(expr.offset),
+    }
+}
+
+/// Assembles a program into its memory image.
+pub fn assemble_words(source: &str) -> Result<Vec<isize>, anyhow::Error> {
+    let mut return_labels = 0;
+    let mut items = Vec::new();
+    for (idx, line) in source.lines().enumerate() {
+        let line_items = parse_line(line, &mut return_labels)
+            .map_err(|err| anyhow::anyhow!("line {}: {}", idx + 1, err))?;
+        items.extend(line_items.into_iter().map(|item| (idx + 1, item)));
+    }
+
+    let mut labels = HashMap::new();
+    let mut address = 0;
+    for (line, item) in &items {
+        if let Item::Label(label) = item {
+            if labels.insert(label.clone(), address).is_some() {
+                return Err(anyhow::anyhow!(
+                    "line {}: duplicate label {:?}",
+                    line,
+                    label
+                ));
+            }
+        }
+        if let Item::Address(expected) = item {
+            if *expected != address {
+                return Err(anyhow::anyhow!(
+                    "line {}: expected address {}, got {}",
+                    line,
+                    expected,
+                    address
+                ));
+            }
+        }
+        address += item.size();
+    }
+
+    let mut words = Vec::with_capacity(address);
+    for (line, item) in &items {
+        let with_line = |err: anyhow::Error| anyhow::anyhow!("line {}: {}", line, err);
+        match item {
+            Item::Label(_) | Item::Address(_) => {}
+            Item::Instruction(opcode, params) => {
+                let mut modes = [Mode::Position; 3];
+                for (mode, param) in modes.iter_mut().zip(params) {
+                    *mode = param.mode;
+                }
+                if let Some(written) = opcode.written_param() {
+                    if modes[written] == Mode::Immediate {
+                        return Err(anyhow::anyhow!(
+                            "line {}: {} can't write to an immediate",
+                            line,
+                            opcode.mnemonic()
+                        ));
+                    }
+                }
+                words.push(isize::from(Instruction::new(*opcode, modes)));
+                for param in params {
+                    words.push(resolve(&param.expr, &labels).map_err(with_line)?);
+                }
+            }
+            Item::Data(values) => {
+                for value in values {
+                    words.push(resolve(value, &labels).map_err(with_line)?);
+                }
+            }
+        }
+    }
+    Ok(words)
+}
+
+pub fn assemble(source: &str) -> Result<Computer, anyhow::Error> {
+    assemble_words(source).map(|words| words.as_slice().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{disasm::disassemble, RunResult};
+    use super::*;
+
+    fn outputs(computer: &mut Computer, input: &[isize]) -> Vec<isize> {
+        let mut input = input.iter().copied();
+        let mut next_input = None;
+        let mut outputs = Vec::new();
+        loop {
+            match computer.run(next_input.take()).unwrap() {
+                RunResult::Finished => return outputs,
+                RunResult::WaitingForInput => {
+                    next_input = Some(input.next().expect("should have enough input"))
+                }
+                RunResult::Output(output) => outputs.push(output),
+            }
+        }
+    }
+
+    #[test]
+    fn test_instructions() {
+        assert_eq!(
+            assemble_words("add [0], #5 -> [rb+3]\nmul [rb-1], [rb], [7]\nhlt").unwrap(),
+            vec![21001, 0, 5, 3, 2202, -1, 0, 7, 99]
+        );
+        assert_eq!(
+            assemble_words("IN -> [x]\nOUT [x]\nx: .data 0").unwrap(),
+            vec![3, 4, 4, 4, 0]
+        );
+    }
+
+    #[test]
+    fn test_data() {
+        assert_eq!(
+            assemble_words("a: .data 1, -2, \"a;\\n\", a+1 ; comment").unwrap(),
+            vec![1, -2, 97, 59, 10, 1]
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(assemble_words("add #1, #2").is_err());
+        assert!(assemble_words("add #1, #2 -> #3").is_err());
+        assert!(assemble_words("out 5").is_err());
+        assert!(assemble_words("jmp #nowhere").is_err());
+        assert!(assemble_words("a: hlt\na: hlt").is_err());
+        assert!(assemble_words("hlt\n0002: hlt").is_err());
+        assert!(assemble_words("frobnicate #1").is_err());
+    }
+
+    #[test]
+    fn test_subroutines() {
+        // prints the sum and the product of every pair of inputs
+        let mut computer = assemble(
+            "
+                    arb #stack
+            loop:   in -> [a]
+                    in -> [b]
+                    push [a]
+                    push [b]
+                    call #sum_and_product
+                    pop -> [a]
+                    pop -> [b]
+                    out [b]
+                    out [a]
+                    jmp #loop
+
+            ; replaces the two arguments on top of the stack with their sum and product
+            sum_and_product:
+                    add [rb-3], [rb-2] -> [rb+0]
+                    mul [rb-3], [rb-2] -> [rb-2]
+                    mov [rb+0] -> [rb-3]
+                    ret
+
+            a:      .data 0
+            b:      .data 0
+            stack:
+            ",
+        )
+        .unwrap();
+        // the loop only ends when input runs out, so stop right before that
+        let mut results = Vec::new();
+        for (a, b) in [(3, 4), (-2, 5)] {
+            assert_eq!(computer.run(None).unwrap(), RunResult::WaitingForInput);
+            assert_eq!(computer.run(Some(a)).unwrap(), RunResult::WaitingForInput);
+            results.push(computer.run(Some(b)).unwrap());
+            results.push(computer.run(None).unwrap());
+        }
+        assert_eq!(
+            results,
+            vec![
+                RunResult::Output(7),
+                RunResult::Output(12),
+                RunResult::Output(3),
+                RunResult::Output(-10),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_disassembly_round_trip() {
+        const SOURCE: &str = "
+                    arb #stack
+                    in -> [x]
+                    push [x]
+                    call #square
+                    pop -> [x]
+                    out [x]
+                    eq [x], #0 -> [flag]
+                    jf [flag], #done
+                    out #-1
+            done:   hlt
+            square: mul [rb-2], [rb-2] -> [rb-2]
+                    ret
+            x:      .data 0
+            flag:   .data 0, \"ok\"
+            stack:
+        ";
+        let computer = assemble(SOURCE).unwrap();
+        let listing = disassemble(&computer).to_string();
+        assert!(
+            listing.contains(": MUL [rb-2], [rb-2] -> [rb-2] ; jump target"),
+            "{}",
+            listing
+        );
+        let reassembled = assemble(&listing).unwrap();
+        assert_eq!(
+            (0..computer.mem_len())
+                .map(|idx| reassembled.get_mem(idx))
+                .collect::<Vec<_>>(),
+            (0..computer.mem_len())
+                .map(|idx| computer.get_mem(idx))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(reassembled.mem_len(), computer.mem_len());
+        assert_eq!(outputs(&mut reassembled.clone(), &[3]), vec![9]);
+        assert_eq!(outputs(&mut reassembled.clone(), &[0]), vec![0, -1]);
+    }
+}
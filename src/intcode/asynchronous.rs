@@ -0,0 +1,96 @@
+//! Async driver: input is awaited from a [`Stream`] and output sent into a
+//! [`Sink`], so any number of machines can run cooperatively on a single
+//! threaded executor, each one yielding whenever it waits for input or for
+//! its output to be accepted.
+
+use futures::{Sink, SinkExt, Stream, StreamExt};
+
+use super::io::RunError;
+use super::memory::Memory;
+use super::{Computer, RunResult, StepResult};
+
+impl<M: Memory> Computer<M> {
+    /// Runs until the program halts or `input` ends, sending everything it
+    /// outputs into `output`.
+    ///
+    /// Returns [`RunResult::Finished`] or [`RunResult::WaitingForInput`].
+    pub async fn run_async<I, O>(
+        &mut self,
+        mut input: I,
+        mut output: O,
+    ) -> Result<RunResult<M::Word>, RunError<O::Error>>
+    where
+        I: Stream<Item = M::Word> + Unpin,
+        O: Sink<M::Word> + Unpin,
+    {
+        let mut value = None;
+        loop {
+            match self.step(&mut value)?.result {
+                StepResult::Executed => {}
+                StepResult::WaitingForInput => match input.next().await {
+                    Some(read) => value = Some(read),
+                    None => return Ok(RunResult::WaitingForInput),
+                },
+                StepResult::Output(written) => output.send(written).await.map_err(RunError::Io)?,
+                StepResult::Finished => return Ok(RunResult::Finished),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::channel::mpsc;
+    use futures::executor::block_on;
+    use futures::future::join_all;
+
+    use super::super::IntcodeError;
+    use super::*;
+
+    // Day 07 part 2 example, best phase settings give 139629729
+    const AMPLIFIER: &str =
+        "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5";
+
+    #[test]
+    fn test_feedback_loop() {
+        let phases = [9, 8, 7, 6, 5];
+        let (senders, mut receivers): (Vec<_>, Vec<_>) =
+            phases.iter().map(|_| mpsc::unbounded()).unzip();
+        for (sender, phase) in senders.iter().zip(phases) {
+            sender.unbounded_send(phase).unwrap();
+        }
+        senders[0].unbounded_send(0).unwrap();
+
+        let mut amplifiers: Vec<Computer> =
+            phases.iter().map(|_| AMPLIFIER.parse().unwrap()).collect();
+        let runs = amplifiers
+            .iter_mut()
+            .zip(receivers.iter_mut())
+            .enumerate()
+            .map(|(idx, (amplifier, input))| {
+                let output = senders[(idx + 1) % phases.len()].clone();
+                amplifier.run_async(input, output)
+            });
+        for result in block_on(join_all(runs)) {
+            assert_eq!(result.unwrap(), RunResult::Finished);
+        }
+        drop(senders);
+        assert_eq!(block_on(receivers[0].next()), Some(139629729));
+    }
+
+    #[test]
+    fn test_errors() {
+        let (sender, receiver) = mpsc::unbounded();
+        drop(receiver);
+        let mut c: Computer = "104,1,99".parse().unwrap();
+        let result = block_on(c.run_async(futures::stream::empty(), sender));
+        assert!(matches!(result, Err(RunError::Io(_))));
+
+        let mut c: Computer = "3,0,42".parse().unwrap();
+        let result = block_on(c.run_async(futures::stream::iter([1]), Vec::new()));
+        assert!(matches!(
+            result,
+            Err(RunError::Fault(IntcodeError::UnknownOpcode { ip: 2, .. }))
+        ));
+    }
+}
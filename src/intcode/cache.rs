@@ -0,0 +1,90 @@
+//! Opt-in cache of decoded instructions, so that hot loops skip the
+//! divisions of [`Instruction::try_from`].
+//!
+//! Entries are keyed by address and dropped whenever the program (or
+//! [`Computer::get_mem_mut`]) writes to their cell. The cache is shared
+//! between forks of a machine until one of them changes it, and isn't
+//! serialized.
+
+use std::sync::Arc;
+
+use super::memory::Memory;
+use super::{Computer, Instruction};
+
+/// Instructions past this address are decoded every time, so that a jump
+/// into a huge sparse memory doesn't allocate a huge cache.
+const MAX_CACHED_ADDRESS: usize = 1 << 20;
+
+#[derive(Clone, Debug, Default)]
+pub(super) struct DecodeCache {
+    instructions: Arc<Vec<Option<Instruction>>>,
+}
+
+impl DecodeCache {
+    #[inline]
+    pub(super) fn get(&self, address: usize) -> Option<Instruction> {
+        self.instructions.get(address).copied().flatten()
+    }
+
+    pub(super) fn insert(&mut self, address: usize, instruction: Instruction) {
+        if address >= MAX_CACHED_ADDRESS {
+            return;
+        }
+        let instructions = Arc::make_mut(&mut self.instructions);
+        if instructions.len() <= address {
+            instructions.resize(address + 1, None);
+        }
+        instructions[address] = Some(instruction);
+    }
+
+    #[inline]
+    pub(super) fn invalidate(&mut self, address: usize) {
+        if self.get(address).is_some() {
+            Arc::make_mut(&mut self.instructions)[address] = None;
+        }
+    }
+}
+
+impl<M: Memory> Computer<M> {
+    /// Caches decoded instructions until their cells are written.
+    pub fn with_decode_cache(mut self) -> Self {
+        self.decode_cache = Some(Default::default());
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::memory::SparseMemory;
+    use super::super::RunResult;
+    use super::*;
+
+    #[test]
+    fn test_invalidation() {
+        // increments its first instruction turning ADD into MUL and runs it
+        // again before outputting its result, 1 * 1 (a stale ADD gives 2)
+        let program = "1101,1,1,20,1001,0,1,0,1008,0,1103,21,1006,21,0,4,20,99,0,0,0,0";
+        let mut c: Computer = program.parse::<Computer>().unwrap().with_decode_cache();
+        assert_eq!(c.run(None).unwrap(), RunResult::Output(1));
+
+        // outputs 1 forever, until poked from outside
+        let mut c: Computer = "104,1,1105,1,0"
+            .parse::<Computer>()
+            .unwrap()
+            .with_decode_cache();
+        assert_eq!(c.run(None).unwrap(), RunResult::Output(1));
+        assert_eq!(c.run(None).unwrap(), RunResult::Output(1));
+        let mut fork = c.clone();
+        c.set_mem(2, 99).unwrap();
+        assert_eq!(c.run(None).unwrap(), RunResult::Finished);
+        assert_eq!(fork.run(None).unwrap(), RunResult::Output(1));
+    }
+
+    #[test]
+    fn test_far_addresses() {
+        let mut c: Computer<SparseMemory> = "1105,1,1000000000".parse().unwrap();
+        c = c.with_decode_cache();
+        c.set_mem(1_000_000_000, 99).unwrap();
+        assert_eq!(c.run(None).unwrap(), RunResult::Finished);
+    }
+}
@@ -0,0 +1,368 @@
+//! Compilation tier: straight-line runs of arithmetic, comparison and
+//! relative base instructions are translated into chains of closures with
+//! their parameters and modes bound at compile time (threaded code), so the
+//! hot loops of a program skip decoding altogether. I/O, jumps and anything
+//! that doesn't compile cleanly go through the interpreter ([`Computer::step`]).
+//!
+//! Blocks are compiled the first time execution reaches them. A write into
+//! a compiled cell throws every block away; a program that keeps doing that
+//! stops being compiled and just runs on the interpreter.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Instant;
+
+use super::memory::{Memory, PagedMemory};
+use super::word::Word;
+use super::{Computer, Engine, Instruction, IntcodeError, Mode, Opcode, RunResult, StepResult};
+
+/// Blocks are only compiled below this address, so that a jump into a huge
+/// sparse memory doesn't allocate huge tables.
+const MAX_COMPILED_ADDRESS: usize = 1 << 20;
+
+/// Code writes after which the program is left to the interpreter.
+const MAX_INVALIDATIONS: usize = 16;
+
+/// Compiled instruction, returns the address it wrote to.
+type Op<M> = Box<dyn Fn(&mut Computer<M>) -> Result<Option<usize>, IntcodeError> + Send + Sync>;
+
+/// Parameter with its mode resolved at compile time.
+enum Param<W> {
+    Position(isize),
+    Immediate(W),
+    Relative(isize),
+}
+
+impl<W: Word> Param<W> {
+    /// `None` for addresses and offsets wider than `isize`, which are left to
+    /// the interpreter.
+    fn new(mode: Mode, raw: W) -> Option<Self> {
+        Some(match mode {
+            Mode::Position => Self::Position(raw.to_isize()?),
+            Mode::Immediate => Self::Immediate(raw),
+            Mode::Relative => Self::Relative(raw.to_isize()?),
+        })
+    }
+
+    #[inline]
+    fn address<M: Memory<Word = W>>(&self, c: &Computer<M>) -> Result<Option<usize>, IntcodeError> {
+        match self {
+            Self::Position(address) => c.address(*address).map(Some),
+            Self::Immediate(_) => Ok(None),
+            Self::Relative(offset) => c.relative_address(*offset).map(Some),
+        }
+    }
+
+    #[inline]
+    fn read<M: Memory<Word = W>>(&self, c: &Computer<M>) -> Result<W, IntcodeError> {
+        match self {
+            Self::Immediate(value) => Ok(value.clone()),
+            _ => Ok(c.get_mem(self.address(c)?.expect("not immediate"))),
+        }
+    }
+}
+
+struct Block<M> {
+    ops: Vec<Op<M>>,
+}
+
+enum Slot<M> {
+    Unknown,
+    /// Starts with an instruction left to the interpreter.
+    Interpret,
+    Block(Arc<Block<M>>),
+}
+
+impl<M> Clone for Slot<M> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Unknown => Self::Unknown,
+            Self::Interpret => Self::Interpret,
+            Self::Block(block) => Self::Block(block.clone()),
+        }
+    }
+}
+
+/// [`Computer`] running on compiled blocks where it can.
+#[derive(Clone)]
+pub struct Compiled<M = PagedMemory> {
+    computer: Computer<M>,
+    /// What starts at every address.
+    slots: Vec<Slot<M>>,
+    /// Cells covered by compiled blocks.
+    code: Vec<bool>,
+    invalidations: usize,
+}
+
+impl<M: fmt::Debug> fmt::Debug for Compiled<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Compiled")
+            .field("computer", &self.computer)
+            .field("invalidations", &self.invalidations)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<M> From<Computer<M>> for Compiled<M> {
+    fn from(computer: Computer<M>) -> Self {
+        Self {
+            computer,
+            slots: Vec::new(),
+            code: Vec::new(),
+            invalidations: 0,
+        }
+    }
+}
+
+impl<M> Compiled<M>
+where
+    M: Memory + 'static,
+    M::Word: Send + Sync,
+{
+    pub fn new(computer: Computer<M>) -> Self {
+        computer.into()
+    }
+
+    pub fn into_inner(self) -> Computer<M> {
+        self.computer
+    }
+
+    /// Like [`Computer::get_mem_mut`], dropping compiled code covering the cell.
+    pub fn get_mem_mut(&mut self, idx: usize) -> &mut M::Word {
+        if self.is_code(idx) {
+            self.invalidate();
+        }
+        self.computer.get_mem_mut(idx)
+    }
+
+    /// Like [`Computer::set_mem`], dropping compiled code covering the cell.
+    pub fn set_mem(&mut self, idx: usize, value: M::Word) -> Result<(), IntcodeError> {
+        self.computer.check_memory_limit(idx)?;
+        *self.get_mem_mut(idx) = value;
+        Ok(())
+    }
+
+    /// Whether the program still gets compiled (it didn't overwrite its
+    /// code too often).
+    pub fn is_compiling(&self) -> bool {
+        self.invalidations < MAX_INVALIDATIONS
+    }
+
+    fn is_code(&self, address: usize) -> bool {
+        self.code.get(address).copied().unwrap_or(false)
+    }
+
+    fn invalidate(&mut self) {
+        self.slots.clear();
+        self.code.clear();
+        self.invalidations += 1;
+    }
+
+    /// Compiles the block at `ip` unless that was tried already.
+    fn prepare(&mut self, ip: usize) {
+        if !self.is_compiling() || ip >= MAX_COMPILED_ADDRESS {
+            return;
+        }
+        if self.slots.len() <= ip {
+            self.slots.resize(ip + 1, Slot::Unknown);
+        }
+        if let Slot::Unknown = self.slots[ip] {
+            self.slots[ip] = match compile(&self.computer, ip) {
+                Some((block, end)) => {
+                    if self.code.len() < end {
+                        self.code.resize(end, false);
+                    }
+                    self.code[ip..end].iter_mut().for_each(|cell| *cell = true);
+                    Slot::Block(Arc::new(block))
+                }
+                None => Slot::Interpret,
+            };
+        }
+    }
+}
+
+/// Compiles the instructions starting at `start` up to the first one left
+/// to the interpreter, returns the block and the address behind it.
+fn compile<M>(computer: &Computer<M>, start: usize) -> Option<(Block<M>, usize)>
+where
+    M: Memory + 'static,
+    M::Word: Send + Sync,
+{
+    let mut ops: Vec<Op<M>> = Vec::new();
+    let mut address = start;
+    while address < computer.mem_len() && address < MAX_COMPILED_ADDRESS {
+        let Some(instruction) = computer
+            .get_mem(address)
+            .to_isize()
+            .and_then(|word| Instruction::try_from(word).ok())
+        else {
+            break;
+        };
+        let arity = instruction.opcode().arity();
+        let [a, b, c] = instruction.modes();
+        let param =
+            |offset: usize, mode: Mode| Param::new(mode, computer.get_mem(address + offset));
+        let next = address + 1 + arity;
+        let ip = address;
+        let opcode = instruction.opcode();
+        let op: Op<M> = match instruction.opcode() {
+            Opcode::Add | Opcode::Mul | Opcode::LessThan | Opcode::Equals
+                if c != Mode::Immediate =>
+            {
+                let compute: fn(&M::Word, &M::Word) -> Option<M::Word> = match instruction.opcode()
+                {
+                    Opcode::Add => |a, b| a.checked_add(b),
+                    Opcode::Mul => |a, b| a.checked_mul(b),
+                    Opcode::LessThan => |a, b| Some(Word::from_isize(if a < b { 1 } else { 0 })),
+                    _ => |a, b| Some(Word::from_isize(if a == b { 1 } else { 0 })),
+                };
+                let (Some(a), Some(b), Some(c)) = (param(1, a), param(2, b), param(3, c)) else {
+                    break;
+                };
+                Box::new(move |computer: &mut Computer<M>| {
+                    computer.idx = ip;
+                    let (a, b) = (a.read(computer)?, b.read(computer)?);
+                    let target = c.address(computer)?.expect("not immediate");
+                    computer.executed(ip, opcode);
+                    let result = compute(&a, &b).ok_or_else(|| computer.fault_overflow())?;
+                    computer.write_to(target, result)?;
+                    computer.idx = next;
+                    Ok(Some(target))
+                })
+            }
+            Opcode::AdjustRelativeBase => {
+                let Some(a) = param(1, a) else {
+                    break;
+                };
+                Box::new(move |computer: &mut Computer<M>| {
+                    computer.idx = ip;
+                    let offset = a.read(computer)?;
+                    computer.executed(ip, opcode);
+                    computer.adjust_relative_base(&offset)?;
+                    computer.idx = next;
+                    Ok(None)
+                })
+            }
+            _ => break,
+        };
+        ops.push(op);
+        address = next;
+    }
+    (!ops.is_empty()).then_some((Block { ops }, address))
+}
+
+impl<M> Engine for Compiled<M>
+where
+    M: Memory + 'static,
+    M::Word: Send + Sync,
+{
+    type Memory = M;
+
+    fn run(&mut self, mut input: Option<M::Word>) -> Result<RunResult<M::Word>, IntcodeError> {
+        loop {
+            let ip = self.computer.idx;
+            self.prepare(ip);
+            if let Some(Slot::Block(block)) = self.slots.get(ip) {
+                let started = self.computer.profile.is_some().then(Instant::now);
+                let mut wrote_code = false;
+                for op in &block.ops {
+                    if let Some(address) = op(&mut self.computer)? {
+                        if self.code.get(address).copied().unwrap_or(false) {
+                            wrote_code = true;
+                            break;
+                        }
+                    }
+                }
+                if let (Some(profile), Some(started)) = (&mut self.computer.profile, started) {
+                    profile.stepped(started, false);
+                }
+                if wrote_code {
+                    self.invalidate();
+                }
+                continue;
+            }
+
+            let event = self.computer.step(&mut input)?;
+            if let Some(write) = &event.write {
+                if self.is_code(write.address) {
+                    self.invalidate();
+                }
+            }
+            match event.result {
+                StepResult::Executed => {}
+                StepResult::WaitingForInput => return Ok(RunResult::WaitingForInput),
+                StepResult::Output(output) => return Ok(RunResult::Output(output)),
+                StepResult::Finished => return Ok(RunResult::Finished),
+            }
+        }
+    }
+
+    /// Runs just the first instruction of the block at `ip`, compiling
+    /// one that starts there if needed.
+    fn step(
+        &mut self,
+        input: &mut Option<M::Word>,
+    ) -> Result<(StepResult<M::Word>, Option<usize>), IntcodeError> {
+        let ip = self.computer.idx;
+        self.prepare(ip);
+        let (result, write) = match self.slots.get(ip) {
+            Some(Slot::Block(block)) => {
+                let block = block.clone();
+                let started = self.computer.profile.is_some().then(Instant::now);
+                let write = block.ops[0](&mut self.computer)?;
+                if let (Some(profile), Some(started)) = (&mut self.computer.profile, started) {
+                    profile.stepped(started, false);
+                }
+                (StepResult::Executed, write)
+            }
+            _ => {
+                let event = self.computer.step(input)?;
+                (event.result, event.write.map(|write| write.address))
+            }
+        };
+        if write.is_some_and(|address| self.is_code(address)) {
+            self.invalidate();
+        }
+        Ok((result, write))
+    }
+
+    fn computer(&self) -> &Computer<M> {
+        &self.computer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_self_modifying_code() {
+        // increments its first instruction turning ADD into MUL and runs it
+        // again before outputting its result, 1 * 1 (a stale ADD gives 2)
+        let program = "1101,1,1,20,1001,0,1,0,1008,0,1103,21,1006,21,0,4,20,99,0,0,0,0";
+        let mut c = Compiled::new(program.parse::<Computer>().unwrap());
+        assert_eq!(c.run(None).unwrap(), RunResult::Output(1));
+        assert!(c.is_compiling());
+
+        // rewrites the ADD of its loop on every iteration, counting down
+        // from 40 at [30] to 0
+        let program = concat!(
+            "1001,30,-1,30,", // 0: add [30], #-1 -> [30]
+            "1001,5,0,5,",    // 4: add [5], #0 -> [5]
+            "1005,30,0,",     // 8: jt [30], #0
+            "4,30,99",        // 11: out [30]
+        );
+        let mut source: Computer = program.parse().unwrap();
+        source.set_mem(30, 40).unwrap();
+        let mut c = Compiled::new(source.clone());
+        assert_eq!(c.run(None).unwrap(), RunResult::Output(0));
+        assert!(!c.is_compiling());
+        assert_eq!(c.computer().get_mem(30), 0);
+
+        // poking from outside
+        let mut c = Compiled::new("1101,1,1,9,4,9,1105,1,0,0".parse::<Computer>().unwrap());
+        assert_eq!(c.run(None).unwrap(), RunResult::Output(2));
+        c.set_mem(2, 2).unwrap();
+        assert_eq!(c.run(None).unwrap(), RunResult::Output(3));
+    }
+}
@@ -0,0 +1,480 @@
+//! Differential testing of [`Engine`]s: the same program and input script
+//! run on two engines in lock-step, one instruction at a time, comparing
+//! the instruction pointer, relative base, memory writes, outputs and faults
+//! after every step. The first difference is reported together with the
+//! instructions that led to it.
+//!
+//! Stepping doesn't exercise everything an engine does inside
+//! [`Engine::run`] (the compiled tier chains whole blocks there), so
+//! [`compare_runs`] also compares whole runs by their outputs and the machine
+//! they leave behind.
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use super::disasm::Line;
+use super::memory::Memory;
+use super::{Engine, Instruction, IntcodeError, RunResult, StepResult};
+
+/// Instructions shown before a divergence.
+const CONTEXT: usize = 8;
+
+/// What a single step did, as seen from outside the engine.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Observation {
+    pub result: Result<StepResult, IntcodeError>,
+    /// Instruction pointer after the step.
+    pub ip: usize,
+    pub relative_base: isize,
+    /// Address written to and the value it holds now.
+    pub write: Option<(usize, isize)>,
+    pub consumed_input: bool,
+}
+
+impl fmt::Display for Observation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.result {
+            Ok(result) => write!(f, "{:?}", result)?,
+            Err(fault) => write!(f, "fault: {}", fault)?,
+        }
+        write!(f, ", ip {}, rb {}", self.ip, self.relative_base)?;
+        if let Some((address, value)) = self.write {
+            write!(f, ", [{}] = {}", address, value)?;
+        }
+        if self.consumed_input {
+            write!(f, ", consumed input")?;
+        }
+        Ok(())
+    }
+}
+
+/// Why a comparison stopped without finding a difference.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum End {
+    Finished,
+    /// The program wants more input than the script has.
+    WaitingForInput,
+    /// Both engines faulted the same way.
+    Fault(IntcodeError),
+    StepLimit,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Agreement {
+    pub steps: usize,
+    pub outputs: Vec<isize>,
+    pub end: End,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    /// Number of steps both engines agreed on.
+    pub step: usize,
+    /// Where the diverging instruction started.
+    pub ip: usize,
+    /// The last instructions before (and including) the diverging one.
+    pub context: Vec<Line>,
+    pub reference: Observation,
+    pub candidate: Observation,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "engines diverged at step {} (ip {}):",
+            self.step, self.ip
+        )?;
+        for line in &self.context {
+            writeln!(f, "    {}", line)?;
+        }
+        writeln!(f, "reference: {}", self.reference)?;
+        write!(f, "candidate: {}", self.candidate)
+    }
+}
+
+impl std::error::Error for Divergence {}
+
+/// The instruction at `ip` as the disassembler would list it.
+fn line<E: Engine>(engine: &E, ip: usize) -> Line
+where
+    E::Memory: Memory<Word = isize>,
+{
+    let computer = engine.computer();
+    let value = computer.get_mem(ip);
+    match Instruction::try_from(value) {
+        Ok(instruction) => {
+            let mut params = [0; 3];
+            for (idx, param) in params
+                .iter_mut()
+                .enumerate()
+                .take(instruction.opcode().arity())
+            {
+                *param = computer.get_mem(ip + 1 + idx);
+            }
+            Line::Code {
+                address: ip,
+                instruction,
+                params,
+            }
+        }
+        Err(_) => Line::Data { address: ip, value },
+    }
+}
+
+fn observe<E: Engine>(engine: &mut E, input: Option<isize>) -> Observation
+where
+    E::Memory: Memory<Word = isize>,
+{
+    let mut input = input;
+    let had_input = input.is_some();
+    let (result, written) = match engine.step(&mut input) {
+        Ok((result, written)) => (Ok(result), written),
+        Err(fault) => (Err(fault), None),
+    };
+    let computer = engine.computer();
+    Observation {
+        result,
+        ip: computer.ip(),
+        relative_base: computer.relative_base(),
+        write: written.map(|address| (address, computer.get_mem(address))),
+        consumed_input: had_input && input.is_none(),
+    }
+}
+
+/// Runs `reference` and `candidate` side by side for at most `max_steps`
+/// instructions, feeding them `inputs` in order.
+pub fn compare<R, C>(
+    reference: &mut R,
+    candidate: &mut C,
+    inputs: &[isize],
+    max_steps: usize,
+) -> Result<Agreement, Box<Divergence>>
+where
+    R: Engine,
+    R::Memory: Memory<Word = isize>,
+    C: Engine,
+    C::Memory: Memory<Word = isize>,
+{
+    let mut inputs = inputs.iter().copied();
+    let mut next_input = inputs.next();
+    let mut context = VecDeque::with_capacity(CONTEXT);
+    let mut outputs = Vec::new();
+    for step in 0..max_steps {
+        let ip = reference.computer().ip();
+        if context.len() == CONTEXT {
+            context.pop_front();
+        }
+        context.push_back(line(reference, ip));
+
+        let expected = observe(reference, next_input);
+        let actual = observe(candidate, next_input);
+        if expected != actual {
+            return Err(Box::new(Divergence {
+                step,
+                ip,
+                context: context.into(),
+                reference: expected,
+                candidate: actual,
+            }));
+        }
+        if expected.consumed_input {
+            next_input = inputs.next();
+        }
+        let end = match expected.result {
+            Ok(StepResult::Executed) => None,
+            Ok(StepResult::Output(output)) => {
+                outputs.push(output);
+                None
+            }
+            Ok(StepResult::WaitingForInput) => Some(End::WaitingForInput),
+            Ok(StepResult::Finished) => Some(End::Finished),
+            Err(fault) => Some(End::Fault(fault)),
+        };
+        if let Some(end) = end {
+            return Ok(Agreement {
+                steps: step + 1,
+                outputs,
+                end,
+            });
+        }
+    }
+    Ok(Agreement {
+        steps: max_steps,
+        outputs,
+        end: End::StepLimit,
+    })
+}
+
+/// How a whole run ended and the machine it left behind.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Outcome {
+    pub outputs: Vec<isize>,
+    /// Never [`End::StepLimit`], runs aren't limited.
+    pub end: End,
+    pub ip: usize,
+    pub relative_base: isize,
+    pub mem_len: usize,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?}, ip {}, rb {}, {} cells, outputs {:?}",
+            self.end, self.ip, self.relative_base, self.mem_len, self.outputs
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RunDivergence {
+    pub reference: Outcome,
+    pub candidate: Outcome,
+    /// First address the memories differ at, with the reference's and the
+    /// candidate's value.
+    pub memory: Option<(usize, isize, isize)>,
+}
+
+impl fmt::Display for RunDivergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "runs diverged:")?;
+        writeln!(f, "reference: {}", self.reference)?;
+        write!(f, "candidate: {}", self.candidate)?;
+        if let Some((address, expected, actual)) = self.memory {
+            write!(
+                f,
+                "\n[{}] = {} (reference), {} (candidate)",
+                address, expected, actual
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RunDivergence {}
+
+/// Runs `engine` through [`Engine::run`], handing it the next of `inputs`
+/// whenever it asks.
+fn run<E: Engine>(engine: &mut E, inputs: &[isize]) -> Outcome
+where
+    E::Memory: Memory<Word = isize>,
+{
+    let mut inputs = inputs.iter().copied();
+    let mut input = None;
+    let mut outputs = Vec::new();
+    let end = loop {
+        match engine.run(input.take()) {
+            Ok(RunResult::Output(output)) => outputs.push(output),
+            Ok(RunResult::WaitingForInput) => match inputs.next() {
+                Some(next) => input = Some(next),
+                None => break End::WaitingForInput,
+            },
+            Ok(RunResult::Finished) => break End::Finished,
+            Err(fault) => break End::Fault(fault),
+        }
+    };
+    let computer = engine.computer();
+    Outcome {
+        outputs,
+        end,
+        ip: computer.ip(),
+        relative_base: computer.relative_base(),
+        mem_len: computer.mem_len(),
+    }
+}
+
+/// Runs `reference` and then `candidate` to the end, feeding them `inputs`
+/// in order, and compares the outputs, how they ended and their final
+/// instruction pointer, relative base and memory. Both have to stop on their
+/// own, like programs [`compare`] saw end.
+pub fn compare_runs<R, C>(
+    reference: &mut R,
+    candidate: &mut C,
+    inputs: &[isize],
+) -> Result<Outcome, Box<RunDivergence>>
+where
+    R: Engine,
+    R::Memory: Memory<Word = isize>,
+    C: Engine,
+    C::Memory: Memory<Word = isize>,
+{
+    let expected = run(reference, inputs);
+    let actual = run(candidate, inputs);
+    let memory = (0..expected.mem_len.max(actual.mem_len))
+        .map(|address| {
+            (
+                address,
+                reference.computer().get_mem(address),
+                candidate.computer().get_mem(address),
+            )
+        })
+        .find(|(_, expected, actual)| expected != actual);
+    if expected != actual || memory.is_some() {
+        return Err(Box::new(RunDivergence {
+            reference: expected,
+            candidate: actual,
+            memory,
+        }));
+    }
+    Ok(expected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::compiled::Compiled;
+    use super::super::memory::PagedMemory;
+    use super::super::{load, Computer, RunResult};
+    use super::*;
+
+    /// Interpreter adding one to every output.
+    struct OffByOne(Computer);
+
+    impl Engine for OffByOne {
+        type Memory = PagedMemory;
+
+        fn run(&mut self, input: Option<isize>) -> Result<RunResult, IntcodeError> {
+            self.0.run(input)
+        }
+
+        fn step(
+            &mut self,
+            input: &mut Option<isize>,
+        ) -> Result<(StepResult, Option<usize>), IntcodeError> {
+            let (result, write) = Engine::step(&mut self.0, input)?;
+            match result {
+                StepResult::Output(output) => Ok((StepResult::Output(output + 1), write)),
+                result => Ok((result, write)),
+            }
+        }
+
+        fn computer(&self) -> &Computer {
+            &self.0
+        }
+    }
+
+    #[test]
+    fn test_engines_agree() {
+        for (day, inputs, answer) in [
+            ("day05", vec![5], Some(9006327)),
+            ("day09", vec![2], Some(51754)),
+            ("day17", vec![], None),
+        ] {
+            let program = load(day).unwrap();
+            let agreement = compare(
+                &mut program.clone(),
+                &mut Compiled::new(program.clone()),
+                &inputs,
+                usize::MAX,
+            )
+            .unwrap();
+            assert_eq!(agreement.end, End::Finished);
+            if answer.is_some() {
+                assert_eq!(agreement.outputs.last().copied(), answer);
+            }
+
+            let cached = compare(
+                &mut program.clone(),
+                &mut program.clone().with_decode_cache(),
+                &inputs,
+                usize::MAX,
+            )
+            .unwrap();
+            assert_eq!(cached, agreement);
+
+            let outcome = compare_runs(
+                &mut program.clone(),
+                &mut Compiled::new(program.clone()),
+                &inputs,
+            )
+            .unwrap();
+            assert_eq!(outcome.end, End::Finished);
+            assert_eq!(outcome.outputs, agreement.outputs);
+        }
+    }
+
+    /// Compiled engine adding one to the outputs of [`Engine::run`] only,
+    /// which stepping never sees.
+    struct OffByOneRun(Compiled);
+
+    impl Engine for OffByOneRun {
+        type Memory = PagedMemory;
+
+        fn run(&mut self, input: Option<isize>) -> Result<RunResult, IntcodeError> {
+            let result = self.0.run(input);
+            if let Ok(RunResult::Output(output)) = result {
+                return Ok(RunResult::Output(output + 1));
+            }
+            result
+        }
+
+        fn step(
+            &mut self,
+            input: &mut Option<isize>,
+        ) -> Result<(StepResult, Option<usize>), IntcodeError> {
+            self.0.step(input)
+        }
+
+        fn computer(&self) -> &Computer {
+            self.0.computer()
+        }
+    }
+
+    #[test]
+    fn test_divergence() {
+        let program: Computer = "3,9,1001,9,1,9,4,9,99,0".parse().unwrap();
+        let divergence = compare(
+            &mut program.clone(),
+            &mut OffByOne(program.clone()),
+            &[41],
+            100,
+        )
+        .unwrap_err();
+        assert_eq!(divergence.step, 2);
+        assert_eq!(divergence.ip, 6);
+        assert_eq!(divergence.reference.result, Ok(StepResult::Output(42)));
+        assert_eq!(divergence.candidate.result, Ok(StepResult::Output(43)));
+        assert_eq!(
+            divergence.to_string(),
+            "engines diverged at step 2 (ip 6):
+    0000: IN -> [9]
+    0002: ADD [9], #1 -> [9]
+    0006: OUT [9]
+reference: Output(42), ip 8, rb 0
+candidate: Output(43), ip 8, rb 0"
+        );
+
+        let mut candidate = OffByOneRun(Compiled::new(program.clone()));
+        assert!(compare(&mut program.clone(), &mut candidate, &[41], 100).is_ok());
+        let divergence = compare_runs(
+            &mut program.clone(),
+            &mut OffByOneRun(Compiled::new(program.clone())),
+            &[41],
+        )
+        .unwrap_err();
+        assert_eq!(divergence.reference.outputs, [42]);
+        assert_eq!(divergence.candidate.outputs, [43]);
+        assert_eq!(divergence.memory, None);
+
+        let halt: Computer = "99,0".parse().unwrap();
+        let mut poked = halt.clone();
+        poked.set_mem(1, 5).unwrap();
+        let divergence = compare_runs(&mut halt.clone(), &mut poked, &[]).unwrap_err();
+        assert_eq!(divergence.reference, divergence.candidate);
+        assert_eq!(
+            divergence.to_string(),
+            "runs diverged:
+reference: Finished, ip 0, rb 0, 2 cells, outputs []
+candidate: Finished, ip 0, rb 0, 2 cells, outputs []
+[1] = 0 (reference), 5 (candidate)"
+        );
+        let outcome = compare_runs(&mut program.clone(), &mut program.clone(), &[41]).unwrap();
+        assert_eq!(outcome.end, End::Finished);
+        assert_eq!(outcome.outputs, [42]);
+
+        let agreement = compare(&mut program.clone(), &mut program.clone(), &[], 100).unwrap();
+        assert_eq!(agreement.end, End::WaitingForInput);
+        let agreement = compare(&mut program.clone(), &mut program.clone(), &[41], 2).unwrap();
+        assert_eq!(agreement.end, End::StepLimit);
+    }
+}
@@ -0,0 +1,296 @@
+//! Disassembler turning a [`Computer`]'s memory into an annotated listing.
+//!
+//! Code is told apart from data by following the control flow from the entry
+//! points: fallthrough, jumps with immediate targets and return addresses
+//! (immediate values a program stores before an unconditional jump that lands
+//! right behind that jump). Everything that is not reached is listed as data.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use super::memory::Memory;
+use super::{Computer, Instruction, Mode, Opcode};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Line {
+    Code {
+        address: usize,
+        instruction: Instruction,
+        params: [isize; 3],
+    },
+    Data {
+        address: usize,
+        value: isize,
+    },
+}
+
+impl Line {
+    pub fn address(&self) -> usize {
+        match self {
+            Self::Code { address, .. } | Self::Data { address, .. } => *address,
+        }
+    }
+
+    /// Number of memory cells the line covers.
+    pub fn size(&self) -> usize {
+        match self {
+            Self::Code { instruction, .. } => 1 + instruction.opcode().arity(),
+            Self::Data { .. } => 1,
+        }
+    }
+}
+
+fn operand(mode: Mode, param: isize) -> String {
+    match mode {
+        Mode::Position => format!("[{}]", param),
+        Mode::Immediate => format!("#{}", param),
+        Mode::Relative => format!("[rb{:+}]", param),
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Code {
+                address,
+                instruction,
+                params,
+            } => {
+                let opcode = instruction.opcode();
+                write!(f, "{:04}: {}", address, opcode.mnemonic())?;
+                for (idx, (param, mode)) in params
+                    .iter()
+                    .zip(instruction.modes())
+                    .take(opcode.arity())
+                    .enumerate()
+                {
+                    let separator = if Some(idx) == opcode.written_param() {
+                        " -> "
+                    } else if idx == 0 {
+                        " "
+                    } else {
+                        ", "
+                    };
+                    write!(f, "{}{}", separator, operand(mode, *param))?;
+                }
+                Ok(())
+            }
+            Self::Data { address, value } => write!(f, "{:04}: .data {}", address, value),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Listing {
+    pub lines: Vec<Line>,
+    /// Addresses targeted by jumps with immediate targets.
+    pub jump_targets: BTreeSet<usize>,
+}
+
+impl Listing {
+    pub fn line_at(&self, address: usize) -> Option<&Line> {
+        let idx = self
+            .lines
+            .partition_point(|line| line.address() + line.size() <= address);
+        self.lines.get(idx).filter(|line| line.address() <= address)
+    }
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            write!(f, "{}", line)?;
+            if self.jump_targets.contains(&line.address()) {
+                write!(f, " ; jump target")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decodes `address` if it holds an instruction that encodes back to the same word.
+fn decode<M: Memory<Word = isize>>(
+    computer: &Computer<M>,
+    address: usize,
+) -> Option<(Instruction, [isize; 3])> {
+    let word = computer.get_mem(address);
+    let instruction = Instruction::try_from(word).ok()?;
+    let arity = instruction.opcode().arity();
+    if isize::from(instruction) != word || address + arity >= computer.mem_len() {
+        return None;
+    }
+    let mut params = [0; 3];
+    for (idx, param) in params.iter_mut().enumerate().take(arity) {
+        *param = computer.get_mem(address + 1 + idx);
+    }
+    Some((instruction, params))
+}
+
+/// Stored value if the instruction just copies an immediate into memory.
+pub(super) fn stored_constant(instruction: &Instruction, params: &[isize; 3]) -> Option<isize> {
+    let [a, b, _] = instruction.modes();
+    match (instruction.opcode(), a, b, params) {
+        (Opcode::Add, Mode::Immediate, Mode::Immediate, [x, 0, _])
+        | (Opcode::Add, Mode::Immediate, Mode::Immediate, [0, x, _])
+        | (Opcode::Mul, Mode::Immediate, Mode::Immediate, [x, 1, _])
+        | (Opcode::Mul, Mode::Immediate, Mode::Immediate, [1, x, _]) => Some(*x),
+        _ => None,
+    }
+}
+
+pub fn disassemble<M: Memory<Word = isize>>(computer: &Computer<M>) -> Listing {
+    disassemble_from(computer, &[0])
+}
+
+/// Disassembles treating every address in `entry_points` as reachable code.
+pub fn disassemble_from<M: Memory<Word = isize>>(
+    computer: &Computer<M>,
+    entry_points: &[usize],
+) -> Listing {
+    let mut code: BTreeMap<usize, (Instruction, [isize; 3])> = BTreeMap::new();
+    let mut covered = vec![false; computer.mem_len()];
+    let mut jump_targets = BTreeSet::new();
+    let mut stored_constants = BTreeSet::new();
+    let mut unconditional_jump_ends = BTreeSet::new();
+    let mut visited = BTreeSet::new();
+    let mut pending: Vec<usize> = entry_points.to_vec();
+
+    loop {
+        while let Some(address) = pending.pop() {
+            if address >= covered.len() || !visited.insert(address) || covered[address] {
+                continue;
+            }
+            let (instruction, params) = match decode(computer, address) {
+                Some(decoded) => decoded,
+                None => continue,
+            };
+            let end = address + 1 + instruction.opcode().arity();
+            if covered[address..end].iter().any(|covered| *covered) {
+                continue;
+            }
+            covered[address..end]
+                .iter_mut()
+                .for_each(|cell| *cell = true);
+            code.insert(address, (instruction, params));
+
+            if let Some(constant) = stored_constant(&instruction, &params) {
+                stored_constants.insert(constant);
+            }
+            let [cond_mode, target_mode, _] = instruction.modes();
+            let (falls_through, jumps) = match instruction.opcode() {
+                Opcode::Halt => (false, false),
+                Opcode::JumpIfTrue | Opcode::JumpIfFalse if cond_mode == Mode::Immediate => {
+                    let taken = (params[0] != 0) == (instruction.opcode() == Opcode::JumpIfTrue);
+                    (!taken, taken)
+                }
+                Opcode::JumpIfTrue | Opcode::JumpIfFalse => (true, true),
+                _ => (true, false),
+            };
+            if falls_through {
+                pending.push(end);
+            } else if jumps {
+                unconditional_jump_ends.insert(end);
+            }
+            if jumps && target_mode == Mode::Immediate {
+                if let Ok(target) = usize::try_from(params[1]) {
+                    jump_targets.insert(target);
+                    pending.push(target);
+                }
+            }
+        }
+
+        pending.extend(
+            unconditional_jump_ends.iter().filter(|end| {
+                stored_constants.contains(&(**end as isize)) && !visited.contains(*end)
+            }),
+        );
+        if pending.is_empty() {
+            break;
+        }
+    }
+
+    let mut lines = Vec::new();
+    let mut address = 0;
+    while address < computer.mem_len() {
+        let line = match code.get(&address) {
+            Some((instruction, params)) => Line::Code {
+                address,
+                instruction: *instruction,
+                params: *params,
+            },
+            None => Line::Data {
+                address,
+                value: computer.get_mem(address),
+            },
+        };
+        address += line.size();
+        lines.push(line);
+    }
+    Listing {
+        lines,
+        jump_targets,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listing() {
+        let computer: Computer = "22201,3,5,120,1105,1,9,42,-1,3,0,204,-3,99"
+            .parse()
+            .unwrap();
+        let listing = disassemble(&computer);
+        assert_eq!(
+            listing.to_string(),
+            concat!(
+                "0000: ADD [rb+3], [rb+5] -> [rb+120]\n",
+                "0004: JT #1, #9\n",
+                "0007: .data 42\n",
+                "0008: .data -1\n",
+                "0009: IN -> [0] ; jump target\n",
+                "0011: OUT [rb-3]\n",
+                "0013: HLT\n",
+            )
+        );
+        assert_eq!(
+            listing.line_at(5),
+            Some(&Line::Code {
+                address: 4,
+                instruction: Instruction::new(
+                    Opcode::JumpIfTrue,
+                    [Mode::Immediate, Mode::Immediate, Mode::Position]
+                ),
+                params: [1, 9, 0],
+            })
+        );
+    }
+
+    #[test]
+    fn test_return_addresses() {
+        // stores return address 7 into [rb+0], jumps to the subroutine at 8
+        // which jumps back through [rb+0]
+        let computer: Computer = "21101,7,0,0,1105,1,8,99,2105,1,0".parse().unwrap();
+        let listing = disassemble(&computer);
+        assert_eq!(
+            listing.to_string(),
+            concat!(
+                "0000: ADD #7, #0 -> [rb+0]\n",
+                "0004: JT #1, #8\n",
+                "0007: HLT\n",
+                "0008: JT #1, [rb+0] ; jump target\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_non_canonical_words_are_data() {
+        let computer: Computer = "104,1,100099".parse().unwrap();
+        assert_eq!(
+            disassemble(&computer).to_string(),
+            "0000: OUT #1\n0002: .data 100099\n"
+        );
+    }
+}
@@ -0,0 +1,234 @@
+//! Property tests over random instruction words and random programs: the
+//! decoder round-trips, the machine faults instead of panicking, the
+//! compiled tier and the decode cache agree with the interpreter step by
+//! step and over whole runs, and memory and step limits hold. Every case
+//! comes from its own seed, which failures report.
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use super::analysis::analyze;
+use super::compiled::Compiled;
+use super::differential::{compare, compare_runs, End};
+use super::memory::{DenseMemory, Memory, PagedMemory};
+use super::{Computer, DecodeError, Engine, Instruction, IntcodeError, Mode, Opcode};
+
+const CASES: u64 = 2000;
+const MAX_STEPS: usize = 500;
+
+const MODES: [Mode; 3] = [Mode::Position, Mode::Immediate, Mode::Relative];
+
+/// Writes to immediate parameters are rare, so that programs don't fault
+/// on the first one.
+fn random_instruction(rng: &mut StdRng) -> Instruction {
+    let opcode = Opcode::ALL[rng.gen_range(0..Opcode::ALL.len())];
+    let mut modes = [0; 3].map(|_| MODES[rng.gen_range(0..MODES.len())]);
+    if let Some(idx) = opcode.written_param() {
+        if modes[idx] == Mode::Immediate && !rng.gen_ratio(1, 16) {
+            modes[idx] = Mode::Position;
+        }
+    }
+    Instruction::new(opcode, modes)
+}
+
+/// Mostly small values, so that addresses stay close to the program, with
+/// the occasional extreme or undecodable word.
+fn random_word(rng: &mut StdRng, len: usize) -> isize {
+    match rng.gen_range(0..16) {
+        0..=11 => rng.gen_range(0..len as isize + 8),
+        12 | 13 => rng.gen_range(-20..20),
+        14 => [isize::MIN, isize::MIN + 1, -1, isize::MAX - 1, isize::MAX][rng.gen_range(0..5)],
+        _ => rng.gen(),
+    }
+}
+
+/// Mostly whole instructions with their parameters.
+fn random_program(seed: u64) -> (Vec<isize>, Vec<isize>) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let len = rng.gen_range(1..64);
+    let mut program = Vec::with_capacity(len + 3);
+    while program.len() < len {
+        if rng.gen_ratio(7, 8) {
+            let instruction = random_instruction(&mut rng);
+            program.push(instruction.into());
+            for _ in 0..instruction.opcode().arity() {
+                program.push(random_word(&mut rng, len));
+            }
+        } else {
+            program.push(random_word(&mut rng, len));
+        }
+    }
+    let inputs = (0..rng.gen_range(0..8))
+        .map(|_| random_word(&mut rng, len))
+        .collect();
+    (program, inputs)
+}
+
+/// Runs `check` on the case from `seed`, turning a panic into a failure
+/// naming the case.
+fn check_case(seed: u64, check: impl FnOnce(&[isize], &[isize])) {
+    let (program, inputs) = random_program(seed);
+    if let Err(panic) = catch_unwind(AssertUnwindSafe(|| check(&program, &inputs))) {
+        let message = panic
+            .downcast_ref::<String>()
+            .map(String::as_str)
+            .or_else(|| panic.downcast_ref::<&str>().copied())
+            .unwrap_or("?");
+        panic!(
+            "seed {}, program {:?}, inputs {:?}: {}",
+            seed, program, inputs, message
+        );
+    }
+}
+
+#[test]
+fn test_decode_round_trip() {
+    for opcode in Opcode::ALL {
+        for a in MODES {
+            for b in MODES {
+                for c in MODES {
+                    let instruction = Instruction::new(opcode, [a, b, c]);
+                    let word = isize::from(instruction);
+                    assert_eq!(Instruction::try_from(word), Ok(instruction));
+                    assert_eq!(Opcode::from_mnemonic(opcode.mnemonic()), Some(opcode));
+                }
+            }
+        }
+    }
+
+    let mut rng = StdRng::seed_from_u64(0);
+    for _ in 0..100_000 {
+        let word: isize = match rng.gen_range(0..3) {
+            0 => rng.gen_range(-100..100_000),
+            1 => rng.gen_range(0..1_000_000_000),
+            _ => rng.gen(),
+        };
+        match Instruction::try_from(word) {
+            // digits past the modes are ignored
+            Ok(instruction) => {
+                assert!(word > 0, "{}", word);
+                assert_eq!(isize::from(instruction), word % 100_000, "{}", word);
+            }
+            Err(DecodeError::UnknownOpcode(opcode)) => {
+                assert_eq!(opcode, word % 100, "{}", word);
+                assert!(Opcode::try_from(opcode).is_err());
+            }
+            Err(DecodeError::UnknownMode(mode)) => {
+                assert!(Mode::try_from(mode).is_err(), "{}", word);
+                assert!(Opcode::try_from(word % 100).is_ok(), "{}", word);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_random_programs() {
+    for seed in 0..CASES {
+        check_case(seed, |program, inputs| {
+            // the default machine, far writes fault on the default memory limit
+            let computer = Computer::<PagedMemory>::from(program);
+            analyze(&computer).to_dot();
+
+            let reference = compare(
+                &mut computer.clone(),
+                &mut Compiled::new(computer.clone()),
+                inputs,
+                MAX_STEPS,
+            )
+            .unwrap_or_else(|divergence| panic!("compiled: {}", divergence));
+            assert!(reference.steps <= MAX_STEPS);
+            assert_eq!(
+                reference.end == End::StepLimit,
+                reference.steps == MAX_STEPS
+            );
+            let cached = compare(
+                &mut computer.clone(),
+                &mut computer.clone().with_decode_cache(),
+                inputs,
+                MAX_STEPS,
+            )
+            .unwrap_or_else(|divergence| panic!("decode cache: {}", divergence));
+            assert_eq!(cached, reference);
+
+            // programs that end run the same way as a whole, blocks and all
+            if reference.end == End::StepLimit {
+                return;
+            }
+            let outcome = compare_runs(
+                &mut computer.clone(),
+                &mut Compiled::new(computer.clone()),
+                inputs,
+            )
+            .unwrap_or_else(|divergence| panic!("compiled run: {}", divergence));
+            assert_eq!(outcome.end, reference.end);
+            assert_eq!(outcome.outputs, reference.outputs);
+            let cached = compare_runs(
+                &mut computer.clone(),
+                &mut computer.clone().with_decode_cache(),
+                inputs,
+            )
+            .unwrap_or_else(|divergence| panic!("decode cache run: {}", divergence));
+            assert_eq!(cached, outcome);
+        });
+    }
+}
+
+#[test]
+fn test_far_write() {
+    // writes 2 to [2^40]
+    let program = [1101, 1, 1, 1 << 40, 99];
+    let computer = Computer::<PagedMemory>::from(&program[..]);
+    let fault = Err(IntcodeError::MemoryLimit {
+        ip: 0,
+        instruction: 1101,
+        address: 1 << 40,
+    });
+    assert_eq!(computer.clone().run(None), fault);
+    assert_eq!(Compiled::new(computer.clone()).run(None), fault);
+    assert_eq!(Computer::<DenseMemory>::from(&program[..]).run(None), fault);
+}
+
+fn check_memory_limit<M: Memory<Word = isize>>(program: &[isize], inputs: &[isize], limit: usize) {
+    let mut c: Computer<M> = program.into();
+    c = c.with_memory_limit(limit);
+    let footprint = |c: &Computer<M>| c.mem.footprint_after_write(0);
+    let initial = footprint(&c);
+    let mut inputs = inputs.iter().copied();
+    let mut input = inputs.next();
+    for _ in 0..MAX_STEPS {
+        let had_input = input.is_some();
+        match c.step(&mut input) {
+            Ok(event) => {
+                if let Some(write) = event.write {
+                    assert!(c.mem.footprint_after_write(write.address) <= limit);
+                }
+                if event.result == super::StepResult::Finished
+                    || event.result == super::StepResult::WaitingForInput
+                {
+                    break;
+                }
+            }
+            Err(IntcodeError::MemoryLimit { address, .. }) => {
+                assert!(c.mem.footprint_after_write(address) > limit);
+                break;
+            }
+            Err(_) => break,
+        }
+        if had_input && input.is_none() {
+            input = inputs.next();
+        }
+        assert!(footprint(&c) <= limit.max(initial));
+    }
+}
+
+#[test]
+fn test_memory_limit() {
+    for seed in 0..CASES {
+        check_case(seed, |program, inputs| {
+            check_memory_limit::<DenseMemory>(program, inputs, program.len() + 16);
+            check_memory_limit::<PagedMemory>(program, inputs, 1 << 12);
+        });
+    }
+}
@@ -0,0 +1,358 @@
+//! Producers and consumers a [`Computer`] can be wired to with
+//! [`Computer::run_with_io`], which runs a program to completion in one call,
+//! or [`Computer::step_with_io`], which executes a single instruction.
+//! Input is only read when the program asks for it.
+//!
+//! Queues, vectors and channels are used directly, iterators, closures and
+//! `std::io` readers/writers through the [`FromIter`], [`FromFn`], [`ToFn`]
+//! and [`Text`] wrappers.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+use std::sync::mpsc;
+
+use super::memory::Memory;
+use super::word::Word;
+use super::{Computer, IntcodeError, Opcode, RunResult, StepEvent, StepResult};
+
+pub trait IntcodeInput<W = isize> {
+    /// Next value for the program or `None` when there is nothing left.
+    fn read(&mut self) -> io::Result<Option<W>>;
+}
+
+pub trait IntcodeOutput<W = isize> {
+    fn write(&mut self, value: W) -> io::Result<()>;
+}
+
+impl<W, T: IntcodeInput<W> + ?Sized> IntcodeInput<W> for &mut T {
+    fn read(&mut self) -> io::Result<Option<W>> {
+        (**self).read()
+    }
+}
+
+impl<W, T: IntcodeOutput<W> + ?Sized> IntcodeOutput<W> for &mut T {
+    fn write(&mut self, value: W) -> io::Result<()> {
+        (**self).write(value)
+    }
+}
+
+impl<W> IntcodeInput<W> for VecDeque<W> {
+    fn read(&mut self) -> io::Result<Option<W>> {
+        Ok(self.pop_front())
+    }
+}
+
+impl<W> IntcodeOutput<W> for VecDeque<W> {
+    fn write(&mut self, value: W) -> io::Result<()> {
+        self.push_back(value);
+        Ok(())
+    }
+}
+
+impl<W> IntcodeOutput<W> for Vec<W> {
+    fn write(&mut self, value: W) -> io::Result<()> {
+        self.push(value);
+        Ok(())
+    }
+}
+
+/// Blocks until a value arrives, a disconnected channel has nothing left.
+impl<W> IntcodeInput<W> for mpsc::Receiver<W> {
+    fn read(&mut self) -> io::Result<Option<W>> {
+        Ok(self.recv().ok())
+    }
+}
+
+impl<W> IntcodeOutput<W> for mpsc::Sender<W> {
+    fn write(&mut self, value: W) -> io::Result<()> {
+        self.send(value)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "receiver disconnected"))
+    }
+}
+
+/// Input taken from an iterator.
+pub struct FromIter<I>(pub I);
+
+impl<W, I: Iterator<Item = W>> IntcodeInput<W> for FromIter<I> {
+    fn read(&mut self) -> io::Result<Option<W>> {
+        Ok(self.0.next())
+    }
+}
+
+/// Input produced by a closure, called whenever the program reads.
+pub struct FromFn<F>(pub F);
+
+impl<W, F: FnMut() -> Option<W>> IntcodeInput<W> for FromFn<F> {
+    fn read(&mut self) -> io::Result<Option<W>> {
+        Ok((self.0)())
+    }
+}
+
+/// Output passed to a closure.
+pub struct ToFn<F>(pub F);
+
+impl<W, F: FnMut(W)> IntcodeOutput<W> for ToFn<F> {
+    fn write(&mut self, value: W) -> io::Result<()> {
+        (self.0)(value);
+        Ok(())
+    }
+}
+
+/// Numbers as text: read separated by commas or whitespace, written one per line.
+pub struct Text<T> {
+    inner: T,
+    pending: VecDeque<String>,
+}
+
+impl<T> Text<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            pending: Default::default(),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<W: FromStr, R: BufRead> IntcodeInput<W> for Text<R>
+where
+    W::Err: fmt::Display,
+{
+    fn read(&mut self) -> io::Result<Option<W>> {
+        while self.pending.is_empty() {
+            let mut line = String::new();
+            if self.inner.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            self.pending.extend(
+                line.split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|token| !token.is_empty())
+                    .map(str::to_owned),
+            );
+        }
+        let token = self.pending.pop_front().expect("checked above");
+        token.parse().map(Some).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Parsing {:?} failed: {}", token, err),
+            )
+        })
+    }
+}
+
+impl<W: fmt::Display, Wr: Write> IntcodeOutput<W> for Text<Wr> {
+    fn write(&mut self, value: W) -> io::Result<()> {
+        writeln!(self.inner, "{}", value)?;
+        self.inner.flush()
+    }
+}
+
+/// Failure of [`Computer::run_with_io`] or, with the error of its sink,
+/// [`Computer::run_async`].
+#[derive(Debug)]
+pub enum RunError<E = io::Error> {
+    Fault(IntcodeError),
+    Io(E),
+}
+
+impl<E: fmt::Display> fmt::Display for RunError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fault(fault) => write!(f, "{}", fault),
+            Self::Io(err) => write!(f, "I/O failed: {}", err),
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for RunError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Fault(fault) => Some(fault),
+            Self::Io(err) => Some(err),
+        }
+    }
+}
+
+impl RunError {
+    /// The fault of a run with input and output that can't fail (queues,
+    /// vectors and closures), panics on an I/O error.
+    pub fn into_fault(self) -> IntcodeError {
+        match self {
+            Self::Fault(fault) => fault,
+            Self::Io(err) => panic!("I/O that can't fail failed: {}", err),
+        }
+    }
+}
+
+impl<E> From<IntcodeError> for RunError<E> {
+    fn from(fault: IntcodeError) -> Self {
+        Self::Fault(fault)
+    }
+}
+
+impl From<io::Error> for RunError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl<M: Memory> Computer<M> {
+    /// Runs until the program halts or needs input `input` doesn't have,
+    /// passing everything it outputs to `output`.
+    ///
+    /// Returns [`RunResult::Finished`] or [`RunResult::WaitingForInput`].
+    pub fn run_with_io<I, O>(
+        &mut self,
+        mut input: I,
+        mut output: O,
+    ) -> Result<RunResult<M::Word>, RunError>
+    where
+        I: IntcodeInput<M::Word>,
+        O: IntcodeOutput<M::Word>,
+    {
+        loop {
+            match self.step_with_io(&mut input, &mut output)?.result {
+                StepResult::Executed | StepResult::Output(_) => {}
+                StepResult::WaitingForInput => return Ok(RunResult::WaitingForInput),
+                StepResult::Finished => return Ok(RunResult::Finished),
+            }
+        }
+    }
+
+    /// Executes a single instruction like [`Computer::step`], reading its
+    /// input from `input` and passing its output to `output` as well.
+    ///
+    /// Returns the event of a [`StepResult::WaitingForInput`] step when the
+    /// instruction needs input `input` doesn't have.
+    pub fn step_with_io<I, O>(
+        &mut self,
+        mut input: I,
+        mut output: O,
+    ) -> Result<StepEvent<M::Word>, RunError>
+    where
+        I: IntcodeInput<M::Word>,
+        O: IntcodeOutput<M::Word>,
+    {
+        // read ahead, so that only missing input makes the program wait
+        let reads = self
+            .get_mem(self.ip())
+            .to_isize()
+            .is_some_and(|word| Opcode::try_from(word % 100) == Ok(Opcode::Input));
+        let mut value = if reads { input.read()? } else { None };
+        let event = self.step(&mut value)?;
+        if let StepResult::Output(written) = &event.result {
+            output.write(written.clone())?;
+        }
+        Ok(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // outputs the running sum of its inputs
+    const PROGRAM: &str = "3,12,1,12,13,13,4,13,1105,1,0,99,0,0";
+
+    fn computer() -> Computer {
+        PROGRAM.parse().unwrap()
+    }
+
+    #[test]
+    fn test_queues_iterators_and_closures() {
+        let mut input = VecDeque::from([1, 2, 3]);
+        let mut output = Vec::new();
+        let mut c = computer();
+        assert_eq!(
+            c.run_with_io(&mut input, &mut output).unwrap(),
+            RunResult::WaitingForInput
+        );
+        assert_eq!(output, vec![1, 3, 6]);
+
+        let mut sums = VecDeque::new();
+        c.run_with_io(FromIter(4..=5), &mut sums).unwrap();
+        assert_eq!(sums, VecDeque::from([10, 15]));
+
+        let mut next = 10;
+        let mut last = 0;
+        c.run_with_io(
+            FromFn(|| {
+                next -= 5;
+                (next > 0).then_some(next)
+            }),
+            ToFn(|sum| last = sum),
+        )
+        .unwrap();
+        assert_eq!(last, 20);
+    }
+
+    #[test]
+    fn test_channels() {
+        let (input, program_input) = mpsc::channel();
+        let (program_output, output) = mpsc::channel();
+        let handle = std::thread::spawn(move || {
+            computer()
+                .run_with_io(program_input, program_output)
+                .unwrap()
+        });
+        for value in [1, 2, 3] {
+            input.send(value).unwrap();
+            assert!(output.recv().is_ok());
+        }
+        drop(input);
+        assert_eq!(handle.join().unwrap(), RunResult::WaitingForInput);
+    }
+
+    #[test]
+    fn test_text() {
+        let mut output = Text::new(Vec::new());
+        let mut c = computer();
+        c.run_with_io(Text::new("1, 2\n 3\n\n4".as_bytes()), &mut output)
+            .unwrap();
+        assert_eq!(output.into_inner(), b"1\n3\n6\n10\n");
+
+        let err = c
+            .run_with_io(Text::new("5,x".as_bytes()), Vec::new())
+            .unwrap_err();
+        assert!(matches!(err, RunError::Io(err) if err.kind() == io::ErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn test_step() {
+        let mut c = computer();
+        let mut input = VecDeque::from([5]);
+        let mut output = Vec::new();
+        let event = c.step_with_io(&mut input, &mut output).unwrap();
+        assert_eq!(event.ip, 0);
+        assert_eq!(event.result, StepResult::Executed);
+        assert!(input.is_empty());
+        c.step_with_io(&mut input, &mut output).unwrap();
+        let event = c.step_with_io(&mut input, &mut output).unwrap();
+        assert_eq!(event.result, StepResult::Output(5));
+        assert_eq!(output, vec![5]);
+
+        // jumps back and waits for input
+        c.step_with_io(&mut input, &mut output).unwrap();
+        let event = c.step_with_io(&mut input, &mut output).unwrap();
+        assert_eq!(event.ip, 0);
+        assert_eq!(event.result, StepResult::WaitingForInput);
+        assert_eq!(c.ip(), 0);
+    }
+
+    #[test]
+    fn test_faults() {
+        let mut c: Computer = "3,0,4,0,42".parse().unwrap();
+        let mut output = Vec::new();
+        let err = c.run_with_io(VecDeque::from([7]), &mut output).unwrap_err();
+        assert!(matches!(
+            err,
+            RunError::Fault(IntcodeError::UnknownOpcode { ip: 4, .. })
+        ));
+        assert_eq!(output, vec![7]);
+    }
+}
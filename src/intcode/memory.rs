@@ -0,0 +1,317 @@
+//! Memory backends of a [`Computer`](super::Computer).
+//!
+//! - [`PagedMemory`] splits memory into fixed size pages shared between clones
+//!   until one of them writes to a page, so forking a machine costs a pointer
+//!   per page instead of a copy of the whole memory.
+//! - [`DenseMemory`] is a plain vector, the cheapest to read and to clone for
+//!   small programs.
+//! - [`SparseMemory`] only stores the cells that were written, so programs can
+//!   use huge addresses.
+//!
+//! Dense and paged memories allocate every cell up to the highest address
+//! written, so machines using them fault past [`DEFAULT_LIMIT`] cells unless
+//! they set a limit of their own.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use super::word::Word;
+
+const PAGE_BITS: usize = 7;
+pub const PAGE_SIZE: usize = 1 << PAGE_BITS;
+const PAGE_MASK: usize = PAGE_SIZE - 1;
+
+/// Cells the backends that allocate up to the highest address written
+/// (dense and paged) may hold by default, 512 MiB of `isize`s.
+pub const DEFAULT_LIMIT: usize = 1 << 26;
+
+type Page<W> = [W; PAGE_SIZE];
+
+fn zero_page<W: Word>() -> Arc<Page<W>> {
+    Arc::new(std::array::from_fn(|_| W::default()))
+}
+
+/// Storage of a machine's memory. Cells that were never written read as zero.
+pub trait Memory: Clone + fmt::Debug + From<Vec<<Self as Memory>::Word>> {
+    type Word: Word;
+
+    /// Number of cells up to and including the highest one ever written
+    /// (saturating at `usize::MAX`).
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn get(&self, idx: usize) -> Self::Word;
+
+    fn get_mut(&mut self, idx: usize) -> &mut Self::Word;
+
+    /// Number of cells the backend would hold after writing to `idx`, which is
+    /// what memory limits are checked against.
+    fn footprint_after_write(&self, idx: usize) -> usize;
+
+    /// Memory limit of machines that don't set one, see
+    /// [`Computer::with_memory_limit`](super::Computer::with_memory_limit).
+    const DEFAULT_LIMIT: Option<usize> = None;
+
+    fn to_vec(&self) -> Vec<Self::Word> {
+        (0..self.len()).map(|idx| self.get(idx)).collect()
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(from = "Vec<W>", into = "Vec<W>", bound = "W: Word")]
+pub struct PagedMemory<W = isize> {
+    /// Pages that were never written all share a single zeroed page.
+    pages: Vec<Arc<Page<W>>>,
+    len: usize,
+}
+
+impl<W: Word> Memory for PagedMemory<W> {
+    type Word = W;
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    fn get(&self, idx: usize) -> W {
+        self.pages
+            .get(idx >> PAGE_BITS)
+            .map(|page| page[idx & PAGE_MASK].clone())
+            .unwrap_or_default()
+    }
+
+    #[inline]
+    fn get_mut(&mut self, idx: usize) -> &mut W {
+        let page_idx = idx >> PAGE_BITS;
+        if self.pages.len() <= page_idx {
+            self.pages.resize(page_idx + 1, zero_page());
+        }
+        self.len = self.len.max(idx.saturating_add(1));
+        &mut Arc::make_mut(&mut self.pages[page_idx])[idx & PAGE_MASK]
+    }
+
+    fn footprint_after_write(&self, idx: usize) -> usize {
+        self.pages
+            .len()
+            .max((idx >> PAGE_BITS) + 1)
+            .saturating_mul(PAGE_SIZE)
+    }
+
+    const DEFAULT_LIMIT: Option<usize> = Some(DEFAULT_LIMIT);
+}
+
+impl<W> PagedMemory<W> {
+    /// Number of pages this memory doesn't share with any clone.
+    pub fn private_pages(&self) -> usize {
+        self.pages
+            .iter()
+            .filter(|page| Arc::strong_count(page) == 1)
+            .count()
+    }
+}
+
+impl<W: Word> From<&[W]> for PagedMemory<W> {
+    fn from(cells: &[W]) -> Self {
+        let pages = cells
+            .chunks(PAGE_SIZE)
+            .map(|chunk| {
+                Arc::new(std::array::from_fn(|idx| {
+                    chunk.get(idx).cloned().unwrap_or_default()
+                }))
+            })
+            .collect();
+        Self {
+            pages,
+            len: cells.len(),
+        }
+    }
+}
+
+impl<W: Word> From<Vec<W>> for PagedMemory<W> {
+    fn from(cells: Vec<W>) -> Self {
+        cells.as_slice().into()
+    }
+}
+
+impl<W: Word> From<PagedMemory<W>> for Vec<W> {
+    fn from(memory: PagedMemory<W>) -> Self {
+        memory.to_vec()
+    }
+}
+
+impl<W: Word> PartialEq for PagedMemory<W> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && (0..self.len).all(|idx| self.get(idx) == other.get(idx))
+    }
+}
+
+impl<W: Word + Eq> Eq for PagedMemory<W> {}
+
+impl<W: Word> PartialEq<Vec<W>> for PagedMemory<W> {
+    fn eq(&self, other: &Vec<W>) -> bool {
+        self.len == other.len() && other.iter().enumerate().all(|(idx, v)| self.get(idx) == *v)
+    }
+}
+
+impl<W: Word> fmt::Debug for PagedMemory<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries((0..self.len).map(|idx| self.get(idx)))
+            .finish()
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent, bound = "W: Word")]
+pub struct DenseMemory<W = isize>(Vec<W>);
+
+impl<W: Word> Memory for DenseMemory<W> {
+    type Word = W;
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    #[inline]
+    fn get(&self, idx: usize) -> W {
+        self.0.get(idx).cloned().unwrap_or_default()
+    }
+
+    #[inline]
+    fn get_mut(&mut self, idx: usize) -> &mut W {
+        if self.0.len() <= idx {
+            self.0.resize(idx + 1, W::default());
+        }
+        &mut self.0[idx]
+    }
+
+    fn footprint_after_write(&self, idx: usize) -> usize {
+        self.0.len().max(idx.saturating_add(1))
+    }
+
+    const DEFAULT_LIMIT: Option<usize> = Some(DEFAULT_LIMIT);
+
+    fn to_vec(&self) -> Vec<W> {
+        self.0.clone()
+    }
+}
+
+impl<W> From<Vec<W>> for DenseMemory<W> {
+    fn from(cells: Vec<W>) -> Self {
+        Self(cells)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound = "W: Word")]
+pub struct SparseMemory<W = isize> {
+    cells: BTreeMap<usize, W>,
+    len: usize,
+}
+
+impl<W: Word> Memory for SparseMemory<W> {
+    type Word = W;
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn get(&self, idx: usize) -> W {
+        self.cells.get(&idx).cloned().unwrap_or_default()
+    }
+
+    fn get_mut(&mut self, idx: usize) -> &mut W {
+        self.len = self.len.max(idx.saturating_add(1));
+        self.cells.entry(idx).or_default()
+    }
+
+    fn footprint_after_write(&self, idx: usize) -> usize {
+        self.cells.len() + usize::from(!self.cells.contains_key(&idx))
+    }
+}
+
+impl<W> From<Vec<W>> for SparseMemory<W> {
+    fn from(cells: Vec<W>) -> Self {
+        Self {
+            len: cells.len(),
+            cells: cells.into_iter().enumerate().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_write() {
+        let mut memory: PagedMemory = vec![1, 2, 3].into();
+        assert_eq!(memory.len(), 3);
+        assert_eq!(memory.get(2), 3);
+        assert_eq!(memory.get(3), 0);
+        assert_eq!(memory.get(10 * PAGE_SIZE), 0);
+        *memory.get_mut(3 * PAGE_SIZE + 1) = 7;
+        assert_eq!(memory.len(), 3 * PAGE_SIZE + 2);
+        assert_eq!(memory.get(3 * PAGE_SIZE + 1), 7);
+        assert_eq!(memory.to_vec()[..4], [1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn test_copy_on_write() {
+        let cells: Vec<isize> = (0..4 * PAGE_SIZE as isize).collect();
+        let original: PagedMemory = cells.clone().into();
+        let mut fork = original.clone();
+        assert_eq!(fork.private_pages(), 0);
+        *fork.get_mut(PAGE_SIZE + 5) = -1;
+        assert_eq!(fork.private_pages(), 1);
+        assert_eq!(original.private_pages(), 1);
+        assert_eq!(original, cells);
+        assert_eq!(fork.get(PAGE_SIZE + 5), -1);
+        assert_eq!(fork.get(PAGE_SIZE + 6), PAGE_SIZE as isize + 6);
+        assert_ne!(fork, original);
+    }
+
+    #[test]
+    fn test_backends_agree() {
+        fn exercise<M: Memory<Word = isize>>(mut memory: M) -> (Vec<isize>, usize) {
+            *memory.get_mut(5) += 10;
+            *memory.get_mut(300) = -3;
+            let footprint = memory.footprint_after_write(1000);
+            assert_eq!(memory.len(), 301);
+            assert_eq!(memory.get(299), 0);
+            assert_eq!(memory.get(100_000), 0);
+            (memory.to_vec(), footprint)
+        }
+
+        let cells: Vec<isize> = (0..10).collect();
+        let (dense, dense_footprint) = exercise(DenseMemory::from(cells.clone()));
+        let (paged, paged_footprint) = exercise(PagedMemory::from(cells.clone()));
+        let (sparse, sparse_footprint) = exercise(SparseMemory::from(cells));
+        assert_eq!(dense, paged);
+        assert_eq!(dense, sparse);
+        assert_eq!(dense[5], 15);
+        assert_eq!(dense_footprint, 1001);
+        assert_eq!(paged_footprint, 8 * PAGE_SIZE);
+        assert_eq!(sparse_footprint, 12);
+    }
+
+    #[test]
+    fn test_sparse_huge_addresses() {
+        let mut memory: SparseMemory = SparseMemory::default();
+        *memory.get_mut(1_000_000_000_000) = 1;
+        assert_eq!(memory.len(), 1_000_000_000_001);
+        assert_eq!(memory.get(1_000_000_000_000), 1);
+        assert_eq!(memory.footprint_after_write(1_000_000_000_000), 1);
+
+        // the length saturates at the last cell of the address space
+        *memory.get_mut(usize::MAX) = 2;
+        assert_eq!(memory.len(), usize::MAX);
+        assert_eq!(memory.get(usize::MAX), 2);
+    }
+}
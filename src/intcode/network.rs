@@ -0,0 +1,300 @@
+//! Networks of machines whose outputs feed other machines' inputs, like the
+//! amplifier chains and feedback loops of Day 07.
+//!
+//! Nodes are added with their initial inputs (phase settings and the like)
+//! and connected with directed edges: every output of a node is sent to all
+//! of its targets. The network then runs under one of the [`Scheduler`]s
+//! until every node halts, or fails with [`NetworkError::Deadlock`] once
+//! all the remaining ones wait for input nobody is going to send.
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use super::memory::{Memory, PagedMemory};
+use super::{Computer, IntcodeError, StepResult};
+
+pub mod threaded;
+
+pub type NodeId = usize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scheduler {
+    /// Runs the nodes in the order they were added, each until it halts or
+    /// blocks, which drives a chain in a single pass.
+    Serial,
+    /// Nodes take turns, each running until it outputs a value or blocks,
+    /// which keeps the nodes of a feedback loop in lock-step.
+    RoundRobin,
+    /// Runs whichever node has pending input, for arbitrary graphs where
+    /// most nodes are idle most of the time.
+    Worklist,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NetworkError {
+    Fault {
+        node: NodeId,
+        fault: IntcodeError,
+    },
+    /// Nodes that didn't halt, all waiting for input.
+    Deadlock {
+        waiting: Vec<NodeId>,
+    },
+    UnknownNode {
+        node: NodeId,
+        nodes: usize,
+    },
+    /// A node expected to wait for input halted or output a value instead.
+    NotWaiting {
+        node: NodeId,
+    },
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fault { node, fault } => write!(f, "node {} faulted: {}", node, fault),
+            Self::Deadlock { waiting } => {
+                write!(f, "deadlock, nodes {:?} are waiting for input", waiting)
+            }
+            Self::UnknownNode { node, nodes } => {
+                write!(f, "unknown node {} ({} nodes)", node, nodes)
+            }
+            Self::NotWaiting { node } => write!(f, "node {} didn't wait for input", node),
+        }
+    }
+}
+
+impl std::error::Error for NetworkError {}
+
+#[derive(Clone, Debug)]
+struct Node<M: Memory> {
+    computer: Computer<M>,
+    input: VecDeque<M::Word>,
+    outputs: Vec<M::Word>,
+    finished: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct Network<M: Memory = PagedMemory> {
+    nodes: Vec<Node<M>>,
+    /// Targets of every node's outputs.
+    edges: Vec<Vec<NodeId>>,
+}
+
+impl<M: Memory> Default for Network<M> {
+    fn default() -> Self {
+        Self {
+            nodes: Default::default(),
+            edges: Default::default(),
+        }
+    }
+}
+
+impl<M: Memory> Network<M> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_node<I>(&mut self, computer: Computer<M>, initial_input: I) -> NodeId
+    where
+        I: IntoIterator<Item = M::Word>,
+    {
+        self.nodes.push(Node {
+            computer,
+            input: initial_input.into_iter().collect(),
+            outputs: Vec::new(),
+            finished: false,
+        });
+        self.edges.push(Vec::new());
+        self.nodes.len() - 1
+    }
+
+    /// Sends every output of `from` to the input of `to`, both must have been
+    /// added already.
+    pub fn connect(&mut self, from: NodeId, to: NodeId) -> Result<(), NetworkError> {
+        let nodes = self.nodes.len();
+        if let Some(node) = [from, to].into_iter().find(|node| *node >= nodes) {
+            return Err(NetworkError::UnknownNode { node, nodes });
+        }
+        self.edges[from].push(to);
+        Ok(())
+    }
+
+    pub fn send(&mut self, node: NodeId, value: M::Word) {
+        self.nodes[node].input.push_back(value);
+    }
+
+    /// Everything `node` has output so far.
+    pub fn outputs(&self, node: NodeId) -> &[M::Word] {
+        &self.nodes[node].outputs
+    }
+
+    pub fn computer(&self, node: NodeId) -> &Computer<M> {
+        &self.nodes[node].computer
+    }
+
+    pub fn is_finished(&self, node: NodeId) -> bool {
+        self.nodes[node].finished
+    }
+
+    /// Runs until every node halts.
+    pub fn run(&mut self, scheduler: Scheduler) -> Result<(), NetworkError> {
+        match scheduler {
+            Scheduler::Serial => while self.run_each(false)? {},
+            Scheduler::RoundRobin => while self.run_each(true)? {},
+            Scheduler::Worklist => self.run_worklist()?,
+        }
+        let waiting: Vec<NodeId> = (0..self.nodes.len())
+            .filter(|node| !self.nodes[*node].finished)
+            .collect();
+        if waiting.is_empty() {
+            Ok(())
+        } else {
+            Err(NetworkError::Deadlock { waiting })
+        }
+    }
+
+    fn run_each(&mut self, single_output: bool) -> Result<bool, NetworkError> {
+        let mut progress = false;
+        for node in 0..self.nodes.len() {
+            progress |= self.run_node(node, single_output)?;
+        }
+        Ok(progress)
+    }
+
+    fn run_worklist(&mut self) -> Result<(), NetworkError> {
+        let mut ready: VecDeque<NodeId> = (0..self.nodes.len()).collect();
+        let mut queued = vec![true; self.nodes.len()];
+        while let Some(node) = ready.pop_front() {
+            queued[node] = false;
+            self.run_node(node, false)?;
+            for &target in &self.edges[node] {
+                let target_node = &self.nodes[target];
+                if !queued[target] && !target_node.finished && !target_node.input.is_empty() {
+                    queued[target] = true;
+                    ready.push_back(target);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs `id` until it halts, blocks or (with `single_output`) outputs a
+    /// value, returns whether it executed anything.
+    fn run_node(&mut self, id: NodeId, single_output: bool) -> Result<bool, NetworkError> {
+        let mut progress = false;
+        while !self.nodes[id].finished {
+            let node = &mut self.nodes[id];
+            let event = node
+                .computer
+                .step_with_io(&mut node.input, &mut node.outputs)
+                .map_err(|err| NetworkError::Fault {
+                    node: id,
+                    fault: err.into_fault(),
+                })?;
+            match event.result {
+                StepResult::Executed => {}
+                StepResult::WaitingForInput => break,
+                StepResult::Output(value) => {
+                    for &target in &self.edges[id] {
+                        self.nodes[target].input.push_back(value.clone());
+                    }
+                    if single_output {
+                        return Ok(true);
+                    }
+                }
+                StepResult::Finished => node.finished = true,
+            }
+            progress = true;
+        }
+        Ok(progress)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOUBLE: &str = "3,0,1002,0,2,0,4,0,99";
+    const ECHO: &str = "3,0,4,0,99";
+    // Day 07 part 2 example, phase settings 9,8,7,6,5 give 139629729
+    const AMPLIFIER: &str =
+        "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5";
+
+    fn chain(program: &str, initial_inputs: &[&[isize]], closed: bool) -> Network {
+        let mut network = Network::new();
+        for initial_input in initial_inputs {
+            network.add_node(program.parse().unwrap(), initial_input.iter().copied());
+        }
+        for node in 1..initial_inputs.len() {
+            network.connect(node - 1, node).unwrap();
+        }
+        if closed {
+            network.connect(initial_inputs.len() - 1, 0).unwrap();
+        }
+        network
+    }
+
+    #[test]
+    fn test_connect_unknown_node() {
+        let mut network = chain(ECHO, &[&[], &[]], false);
+        let err = network.connect(0, 2).unwrap_err();
+        assert_eq!(err, NetworkError::UnknownNode { node: 2, nodes: 2 });
+        assert_eq!(err.to_string(), "unknown node 2 (2 nodes)");
+        assert_eq!(
+            network.connect(3, 0),
+            Err(NetworkError::UnknownNode { node: 3, nodes: 2 })
+        );
+    }
+
+    #[test]
+    fn test_chain() {
+        for scheduler in [
+            Scheduler::Serial,
+            Scheduler::RoundRobin,
+            Scheduler::Worklist,
+        ] {
+            let mut network = chain(DOUBLE, &[&[3], &[], &[]], false);
+            network.run(scheduler).unwrap();
+            assert_eq!(network.outputs(2), &[24]);
+        }
+    }
+
+    #[test]
+    fn test_feedback_loop() {
+        for scheduler in [
+            Scheduler::Serial,
+            Scheduler::RoundRobin,
+            Scheduler::Worklist,
+        ] {
+            let phases: [&[isize]; 5] = [&[9, 0], &[8], &[7], &[6], &[5]];
+            let mut network = chain(AMPLIFIER, &phases, true);
+            network.run(scheduler).unwrap();
+            assert_eq!(network.outputs(4).last(), Some(&139629729));
+            assert!((0..5).all(|node| network.is_finished(node)));
+        }
+    }
+
+    #[test]
+    fn test_deadlock_and_faults() {
+        let mut network = chain(ECHO, &[&[], &[]], true);
+        assert_eq!(
+            network.run(Scheduler::Worklist),
+            Err(NetworkError::Deadlock {
+                waiting: vec![0, 1]
+            })
+        );
+        network.send(1, 7);
+        network.run(Scheduler::Worklist).unwrap();
+        assert_eq!(network.outputs(0), &[7]);
+
+        let mut network: Network = Network::new();
+        network.add_node(ECHO.parse().unwrap(), [1]);
+        let broken = network.add_node("42".parse().unwrap(), []);
+        assert!(matches!(
+            network.run(Scheduler::Serial),
+            Err(NetworkError::Fault { node, .. }) if node == broken
+        ));
+    }
+}
@@ -0,0 +1,302 @@
+//! Runs every node of a [`Network`] on its own thread, with edges turned into
+//! `std::sync::mpsc` channels.
+//!
+//! The supervisor (the calling thread) only waits: nodes report when they
+//! block, halt or fault, and count the values they send and receive, so a
+//! deadlock is every running node blocked with nothing left in its channel.
+//! Blocked nodes are then told to stop and their machines are left waiting
+//! for input, so the network can be fed and run again.
+
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+use aoc_helpers::anyhow;
+
+use super::super::io::FromFn;
+use super::super::memory::Memory;
+use super::super::StepResult;
+use super::{Network, NetworkError, Node, NodeId};
+
+enum Message<W> {
+    Value(W),
+    Stop,
+}
+
+struct State {
+    blocked: usize,
+    finished: Vec<bool>,
+    /// Values sent to every node and not received yet.
+    pending: Vec<usize>,
+    fault: Option<NetworkError>,
+    panicked: Option<NodeId>,
+}
+
+impl State {
+    fn settled(&self) -> bool {
+        let running: Vec<NodeId> = (0..self.finished.len())
+            .filter(|node| !self.finished[*node])
+            .collect();
+        self.fault.is_some()
+            || self.panicked.is_some()
+            || (self.blocked == running.len()
+                && running.iter().all(|node| self.pending[*node] == 0))
+    }
+}
+
+struct Supervisor {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+impl Supervisor {
+    fn update<F: FnOnce(&mut State)>(&self, f: F) {
+        f(&mut self.state.lock().unwrap());
+        self.changed.notify_one();
+    }
+}
+
+/// Reports a node whose thread panicked, the supervisor would wait for it
+/// forever otherwise.
+struct PanicGuard<'a> {
+    node: NodeId,
+    supervisor: &'a Supervisor,
+}
+
+impl Drop for PanicGuard<'_> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.supervisor
+                .update(|state| state.panicked = Some(self.node));
+        }
+    }
+}
+
+impl<M> Network<M>
+where
+    M: Memory + Send,
+    M::Word: Send,
+{
+    /// Runs every node on its own thread until all of them halt.
+    ///
+    /// Faults and deadlocks are reported as [`NetworkError`]s (recoverable
+    /// with `downcast_ref`), a panicking node as a plain error.
+    pub fn run_threaded(&mut self) -> Result<(), anyhow::Error> {
+        let (senders, inboxes): (Vec<_>, Vec<_>) =
+            self.nodes.iter().map(|_| mpsc::channel()).unzip();
+        let supervisor = Supervisor {
+            state: Mutex::new(State {
+                blocked: 0,
+                finished: self.nodes.iter().map(|node| node.finished).collect(),
+                pending: vec![0; self.nodes.len()],
+                fault: None,
+                panicked: None,
+            }),
+            changed: Condvar::new(),
+        };
+
+        thread::scope(|scope| {
+            let edges = &self.edges;
+            let handles: Vec<_> = self
+                .nodes
+                .iter_mut()
+                .zip(inboxes)
+                .enumerate()
+                .filter(|(_, (node, _))| !node.finished)
+                .map(|(id, (node, inbox))| {
+                    let targets = edges[id]
+                        .iter()
+                        .map(|target| (*target, senders[*target].clone()))
+                        .collect();
+                    let supervisor = &supervisor;
+                    scope.spawn(move || run_node(id, node, inbox, targets, supervisor))
+                })
+                .collect();
+
+            let mut state = supervisor.state.lock().unwrap();
+            while !state.settled() {
+                state = supervisor.changed.wait(state).unwrap();
+            }
+            let result = if let Some(node) = state.panicked {
+                Err(anyhow::anyhow!("node {} panicked", node))
+            } else if let Some(fault) = state.fault.clone() {
+                Err(fault.into())
+            } else {
+                let waiting: Vec<NodeId> = (0..state.finished.len())
+                    .filter(|node| !state.finished[*node])
+                    .collect();
+                if waiting.is_empty() {
+                    Ok(())
+                } else {
+                    Err(NetworkError::Deadlock { waiting }.into())
+                }
+            };
+            drop(state);
+
+            for sender in &senders {
+                // nodes that already exited dropped their receivers
+                let _ = sender.send(Message::Stop);
+            }
+            for handle in handles {
+                // panics are already reported in `result`
+                let _ = handle.join();
+            }
+            result
+        })
+    }
+}
+
+fn run_node<M: Memory>(
+    id: NodeId,
+    node: &mut Node<M>,
+    inbox: Receiver<Message<M::Word>>,
+    targets: Vec<(NodeId, Sender<Message<M::Word>>)>,
+    supervisor: &Supervisor,
+) {
+    let _guard = PanicGuard {
+        node: id,
+        supervisor,
+    };
+    loop {
+        let input = FromFn(|| {
+            node.input
+                .pop_front()
+                .or_else(|| receive(id, &inbox, supervisor))
+        });
+        let event = match node.computer.step_with_io(input, &mut node.outputs) {
+            Ok(event) => event,
+            Err(err) => {
+                let fault = err.into_fault();
+                supervisor.update(|state| {
+                    state
+                        .fault
+                        .get_or_insert(NetworkError::Fault { node: id, fault });
+                });
+                break;
+            }
+        };
+        match event.result {
+            StepResult::Executed => {}
+            StepResult::WaitingForInput => break,
+            StepResult::Output(output) => {
+                let mut state = supervisor.state.lock().unwrap();
+                for (target, sender) in &targets {
+                    if sender.send(Message::Value(output.clone())).is_ok() {
+                        state.pending[*target] += 1;
+                    }
+                }
+            }
+            StepResult::Finished => {
+                node.finished = true;
+                supervisor.update(|state| state.finished[id] = true);
+                break;
+            }
+        }
+    }
+    // keep values that arrived after a fault elsewhere stopped the network
+    while let Ok(Message::Value(value)) = inbox.try_recv() {
+        node.input.push_back(value);
+    }
+}
+
+/// Next value from `inbox`, `None` when the node should stop.
+fn receive<W>(id: NodeId, inbox: &Receiver<Message<W>>, supervisor: &Supervisor) -> Option<W> {
+    let message = match inbox.try_recv() {
+        Ok(message) => {
+            let mut state = supervisor.state.lock().unwrap();
+            if let Message::Value(_) = message {
+                state.pending[id] -= 1;
+            }
+            message
+        }
+        Err(_) => {
+            supervisor.update(|state| state.blocked += 1);
+            // the supervisor holds a sender until every node is done
+            let message = inbox.recv().unwrap_or(Message::Stop);
+            let mut state = supervisor.state.lock().unwrap();
+            state.blocked -= 1;
+            if let Message::Value(_) = message {
+                state.pending[id] -= 1;
+            }
+            message
+        }
+    };
+    match message {
+        Message::Value(value) => Some(value),
+        Message::Stop => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Scheduler;
+    use super::*;
+
+    const DOUBLE: &str = "3,0,1002,0,2,0,4,0,99";
+    const ECHO: &str = "3,0,4,0,99";
+    const SUM: &str = "3,0,3,1,1,0,1,0,4,0,99";
+    // Day 07 part 2 example, phase settings 9,8,7,6,5 give 139629729
+    const AMPLIFIER: &str =
+        "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5";
+
+    #[test]
+    fn test_agrees_with_scheduler() {
+        // 0 feeds two doublers that both feed a summing node
+        let mut network: Network = Network::new();
+        let source = network.add_node(ECHO.parse().unwrap(), [5]);
+        let left = network.add_node(DOUBLE.parse().unwrap(), []);
+        let right = network.add_node(DOUBLE.parse().unwrap(), []);
+        let sink = network.add_node(SUM.parse().unwrap(), []);
+        for (from, to) in [(source, left), (source, right), (left, sink), (right, sink)] {
+            network.connect(from, to).unwrap();
+        }
+
+        let mut threaded = network.clone();
+        threaded.run_threaded().unwrap();
+        network.run(Scheduler::Worklist).unwrap();
+        assert_eq!(threaded.outputs(sink), network.outputs(sink));
+        assert_eq!(threaded.outputs(sink), &[20]);
+    }
+
+    #[test]
+    fn test_feedback_loop() {
+        let mut network: Network = Network::new();
+        for phase in [9, 8, 7, 6, 5] {
+            network.add_node(AMPLIFIER.parse().unwrap(), [phase]);
+        }
+        for node in 0..5 {
+            network.connect(node, (node + 1) % 5).unwrap();
+        }
+        network.send(0, 0);
+        network.run_threaded().unwrap();
+        assert_eq!(network.outputs(4).last(), Some(&139629729));
+    }
+
+    #[test]
+    fn test_deadlock_and_faults() {
+        let mut network: Network = Network::new();
+        network.add_node(ECHO.parse().unwrap(), []);
+        network.add_node(ECHO.parse().unwrap(), []);
+        network.connect(0, 1).unwrap();
+        network.connect(1, 0).unwrap();
+        let err = network.run_threaded().unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<NetworkError>(),
+            Some(&NetworkError::Deadlock {
+                waiting: vec![0, 1]
+            })
+        );
+        network.send(1, 7);
+        network.run_threaded().unwrap();
+        assert_eq!(network.outputs(0), &[7]);
+
+        let mut network: Network = Network::new();
+        network.add_node(ECHO.parse().unwrap(), []);
+        let broken = network.add_node("42".parse().unwrap(), []);
+        let err = network.run_threaded().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<NetworkError>(),
+            Some(NetworkError::Fault { node, .. }) if *node == broken
+        ));
+    }
+}
@@ -0,0 +1,353 @@
+//! Packet-switched networks of identical machines, like the one of Day 23.
+//!
+//! Every host is told its address as its first input, sends packets by
+//! outputting `destination, x, y` and reads `-1` whenever no packet is
+//! waiting for it, so hosts never block. A [`Nat`] can watch one address,
+//! remember the last packet sent to it and, once the whole network goes
+//! idle, send that packet again to restart the traffic.
+
+use std::collections::VecDeque;
+
+use super::io::FromFn;
+use super::memory::{Memory, PagedMemory};
+use super::network::NetworkError;
+use super::{Computer, StepResult};
+
+/// Value read by hosts with no packets waiting.
+pub const NO_PACKET: isize = -1;
+
+/// Consecutive idle rounds after which the network counts as idle, hosts
+/// may read [`NO_PACKET`] once before they send something.
+const IDLE_ROUNDS: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Packet {
+    pub source: usize,
+    /// As output by the source, packets to addresses without a host (nor a
+    /// NAT) are dropped.
+    pub destination: isize,
+    pub x: isize,
+    pub y: isize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// A host sent a packet.
+    Sent(Packet),
+    /// The network went idle and the NAT resent its last packet.
+    Restart(Packet),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Nat {
+    /// Address the NAT receives packets on.
+    pub address: usize,
+    /// Host the last packet is resent to when the network goes idle.
+    pub restart: usize,
+}
+
+impl Default for Nat {
+    fn default() -> Self {
+        Self {
+            address: 255,
+            restart: 0,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Host<M> {
+    computer: Computer<M>,
+    queue: VecDeque<isize>,
+    output: Vec<isize>,
+    finished: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct PacketNetwork<M = PagedMemory> {
+    hosts: Vec<Host<M>>,
+    nat: Option<Nat>,
+    nat_packet: Option<Packet>,
+    events: VecDeque<Event>,
+    idle_rounds: usize,
+}
+
+impl<M: Memory<Word = isize>> PacketNetwork<M> {
+    /// Network of `size` copies of `program` with addresses `0..size`.
+    pub fn new(program: &Computer<M>, size: usize) -> Self {
+        let hosts = (0..size)
+            .map(|address| Host {
+                computer: program.clone(),
+                queue: VecDeque::from([address as isize]),
+                output: Vec::with_capacity(3),
+                finished: false,
+            })
+            .collect();
+        Self {
+            hosts,
+            nat: None,
+            nat_packet: None,
+            events: Default::default(),
+            idle_rounds: 0,
+        }
+    }
+
+    pub fn with_nat(mut self, nat: Nat) -> Self {
+        self.nat = Some(nat);
+        self
+    }
+
+    /// Queues a packet for `destination` as if some host sent it.
+    pub fn send(&mut self, destination: usize, x: isize, y: isize) {
+        self.hosts[destination].queue.extend([x, y]);
+    }
+
+    /// Last packet the NAT received.
+    pub fn nat_packet(&self) -> Option<&Packet> {
+        self.nat_packet.as_ref()
+    }
+
+    pub fn computer(&self, address: usize) -> &Computer<M> {
+        &self.hosts[address].computer
+    }
+
+    /// Runs the network until something happens, `None` once every host
+    /// halted.
+    ///
+    /// An idle network without a NAT (or one that hasn't received anything
+    /// yet) is reported as [`NetworkError::Deadlock`].
+    pub fn next_event(&mut self) -> Result<Option<Event>, NetworkError> {
+        while self.events.is_empty() {
+            if self.hosts.iter().all(|host| host.finished) {
+                return Ok(None);
+            }
+            if self.round()? {
+                self.idle_rounds += 1;
+            } else {
+                self.idle_rounds = 0;
+            }
+            if self.idle_rounds >= IDLE_ROUNDS {
+                self.idle_rounds = 0;
+                self.restart()?;
+            }
+        }
+        Ok(self.events.pop_front())
+    }
+
+    /// Runs until `f` returns something for an event, `None` once every
+    /// host halted.
+    pub fn run_until<T, F>(&mut self, mut f: F) -> Result<Option<T>, NetworkError>
+    where
+        F: FnMut(&Event) -> Option<T>,
+    {
+        while let Some(event) = self.next_event()? {
+            if let Some(result) = f(&event) {
+                return Ok(Some(result));
+            }
+        }
+        Ok(None)
+    }
+
+    fn restart(&mut self) -> Result<(), NetworkError> {
+        match (self.nat, self.nat_packet) {
+            (Some(nat), Some(packet)) => {
+                let packet = Packet {
+                    source: nat.address,
+                    destination: nat.restart as isize,
+                    ..packet
+                };
+                self.send(nat.restart, packet.x, packet.y);
+                self.events.push_back(Event::Restart(packet));
+                Ok(())
+            }
+            _ => Err(NetworkError::Deadlock {
+                waiting: (0..self.hosts.len())
+                    .filter(|address| !self.hosts[*address].finished)
+                    .collect(),
+            }),
+        }
+    }
+
+    /// Runs every host until it reads from an empty queue or halts, returns
+    /// whether the round was idle: nothing was received nor output.
+    fn round(&mut self) -> Result<bool, NetworkError> {
+        let mut idle = true;
+        for address in 0..self.hosts.len() {
+            while !self.hosts[address].finished {
+                let host = &mut self.hosts[address];
+                let mut received = false;
+                let mut no_packet = false;
+                let input = FromFn(|| {
+                    Some(match host.queue.pop_front() {
+                        Some(value) => {
+                            received = true;
+                            value
+                        }
+                        None => {
+                            no_packet = true;
+                            NO_PACKET
+                        }
+                    })
+                });
+                let event = host
+                    .computer
+                    .step_with_io(input, &mut host.output)
+                    .map_err(|err| NetworkError::Fault {
+                        node: address,
+                        fault: err.into_fault(),
+                    })?;
+                if received {
+                    idle = false;
+                }
+                if no_packet {
+                    break;
+                }
+                match event.result {
+                    StepResult::Executed | StepResult::WaitingForInput => {}
+                    StepResult::Output(_) => {
+                        idle = false;
+                        if let [destination, x, y] = host.output[..] {
+                            host.output.clear();
+                            self.deliver(Packet {
+                                source: address,
+                                destination,
+                                x,
+                                y,
+                            });
+                        }
+                    }
+                    StepResult::Finished => host.finished = true,
+                }
+            }
+        }
+        Ok(idle && self.hosts.iter().all(|host| host.queue.is_empty()))
+    }
+
+    fn deliver(&mut self, packet: Packet) {
+        self.events.push_back(Event::Sent(packet));
+        let destination = usize::try_from(packet.destination).ok();
+        match (self.nat, destination) {
+            (Some(nat), Some(destination)) if destination == nat.address => {
+                self.nat_packet = Some(packet)
+            }
+            (_, Some(destination)) if destination < self.hosts.len() => {
+                self.send(destination, packet.x, packet.y)
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::asm::assemble;
+    use super::*;
+
+    // passes every packet on to the next address with y incremented, the
+    // last of three hosts sends it to 255
+    const FORWARD: &str = "
+                in -> [addr]
+                add [addr], #1 -> [dest]
+                eq [dest], #3 -> [t]
+                jf [t], #loop
+                mov #255 -> [dest]
+        loop:   in -> [x]
+                eq [x], #-1 -> [t]
+                jt [t], #loop
+                in -> [y]
+                add [y], #1 -> [y]
+                out [dest]
+                out [x]
+                out [y]
+                jmp #loop
+        addr:   .data 0
+        dest:   .data 0
+        t:      .data 0
+        x:      .data 0
+        y:      .data 0
+    ";
+
+    fn network() -> PacketNetwork {
+        let mut network = PacketNetwork::new(&assemble(FORWARD).unwrap(), 3);
+        network.send(0, 7, 0);
+        network
+    }
+
+    #[test]
+    fn test_routing() {
+        let mut network = network();
+        let to_255 = network.run_until(|event| match event {
+            Event::Sent(packet) if packet.destination == 255 => Some(*packet),
+            _ => None,
+        });
+        assert_eq!(
+            to_255,
+            Ok(Some(Packet {
+                source: 2,
+                destination: 255,
+                x: 7,
+                y: 3
+            }))
+        );
+        assert_eq!(
+            network.next_event(),
+            Err(NetworkError::Deadlock {
+                waiting: vec![0, 1, 2]
+            })
+        );
+    }
+
+    #[test]
+    fn test_nat() {
+        let mut network = network().with_nat(Nat::default());
+        let mut restarts = Vec::new();
+        network
+            .run_until(|event| match event {
+                Event::Restart(packet) => {
+                    restarts.push(packet.y);
+                    (restarts.len() == 3).then_some(())
+                }
+                Event::Sent(_) => None,
+            })
+            .unwrap();
+        assert_eq!(restarts, vec![3, 6, 9]);
+        assert_eq!(network.nat_packet().map(|packet| packet.y), Some(9));
+    }
+
+    #[test]
+    fn test_slow_host_is_not_idle() {
+        // reads no packet twice before sending one to the NAT, one word per
+        // round, then keeps reading
+        let program = assemble(
+            "
+                    in -> [t]
+                    in -> [t]
+                    in -> [t]
+                    out #255
+                    in -> [t]
+                    out #4
+                    out #5
+            loop:   in -> [t]
+                    jmp #loop
+            t:      .data 0
+            ",
+        )
+        .unwrap();
+        let mut network = PacketNetwork::new(&program, 1).with_nat(Nat::default());
+        let packet = Packet {
+            source: 0,
+            destination: 255,
+            x: 4,
+            y: 5,
+        };
+        assert_eq!(network.next_event(), Ok(Some(Event::Sent(packet))));
+        assert_eq!(
+            network.next_event(),
+            Ok(Some(Event::Restart(Packet {
+                source: 255,
+                destination: 0,
+                ..packet
+            })))
+        );
+    }
+}
@@ -0,0 +1,263 @@
+//! Opt-in instruction-level profiling.
+//!
+//! Once enabled with [`Computer::with_profiling`], the interpreter and the
+//! [compiled](super::compiled) tier count executions per address and per
+//! [`Opcode`] and time every step (or compiled block), whatever drives the
+//! machine. The time between the program asking for input and getting it is
+//! kept apart. [`Profile::report`] ranks the hottest blocks and instructions
+//! next to their disassembly. The profile isn't serialized.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+use super::analysis::analyze;
+use super::disasm::Line;
+use super::memory::Memory;
+use super::{Computer, Opcode};
+
+/// Addresses past this one are counted in a map, so that a jump into a huge
+/// sparse memory doesn't allocate a huge table.
+const MAX_COUNTED_ADDRESS: usize = 1 << 20;
+
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    executions: Vec<u64>,
+    far_executions: HashMap<usize, u64>,
+    /// By position in [`Opcode::ALL`].
+    opcodes: [u64; Opcode::ALL.len()],
+    instructions: u64,
+    running: Duration,
+    input_wait: Duration,
+    input_waits: u64,
+    waiting_since: Option<Instant>,
+}
+
+impl Profile {
+    pub(super) fn execute(&mut self, ip: usize, opcode: Opcode) {
+        if ip < MAX_COUNTED_ADDRESS {
+            if self.executions.len() <= ip {
+                self.executions.resize(ip + 1, 0);
+            }
+            self.executions[ip] += 1;
+        } else {
+            *self.far_executions.entry(ip).or_default() += 1;
+        }
+        self.opcodes[opcode as usize] += 1;
+        self.instructions += 1;
+    }
+
+    /// Accounts for a step (or block) that started at `started`,
+    /// `waiting_for_input` when it stopped for missing input.
+    pub(super) fn stepped(&mut self, started: Instant, waiting_for_input: bool) {
+        let now = Instant::now();
+        self.running += now - started;
+        if waiting_for_input {
+            self.waiting_since.get_or_insert(now);
+        } else if let Some(since) = self.waiting_since.take() {
+            self.input_wait += started - since;
+            self.input_waits += 1;
+        }
+    }
+
+    /// Times the instruction at `address` was executed.
+    pub fn executions(&self, address: usize) -> u64 {
+        match self.executions.get(address) {
+            Some(count) => *count,
+            None => self.far_executions.get(&address).copied().unwrap_or(0),
+        }
+    }
+
+    pub fn opcode_executions(&self, opcode: Opcode) -> u64 {
+        self.opcodes[opcode as usize]
+    }
+
+    /// Instructions executed in total.
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Time spent executing instructions.
+    pub fn running(&self) -> Duration {
+        self.running
+    }
+
+    /// Time from the program stopping for missing input to it getting some.
+    pub fn input_wait(&self) -> Duration {
+        self.input_wait
+    }
+
+    /// Times the program stopped for missing input and got some later.
+    pub fn input_waits(&self) -> u64 {
+        self.input_waits
+    }
+
+    /// Executed addresses with their counts, hottest first.
+    pub fn hotspots(&self) -> Vec<(usize, u64)> {
+        let mut hotspots: Vec<(usize, u64)> = self
+            .executions
+            .iter()
+            .copied()
+            .enumerate()
+            .chain(self.far_executions.iter().map(|(ip, count)| (*ip, *count)))
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        hotspots.sort_by_key(|(ip, count)| (std::cmp::Reverse(*count), *ip));
+        hotspots
+    }
+
+    /// Report on the `top` hottest blocks and instructions of `program`,
+    /// which should be the program as it was loaded.
+    pub fn report<M: Memory<Word = isize>>(&self, program: &Computer<M>, top: usize) -> String {
+        let share = |count: u64| 100.0 * count as f64 / self.instructions.max(1) as f64;
+        let mut report = String::new();
+        writeln!(
+            report,
+            "{} instructions, {:?} running, {:?} waiting for input",
+            self.instructions, self.running, self.input_wait
+        )
+        .unwrap();
+
+        report.push_str("\nby opcode:\n");
+        let mut opcodes: Vec<(Opcode, u64)> = Opcode::ALL
+            .into_iter()
+            .map(|opcode| (opcode, self.opcode_executions(opcode)))
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        opcodes.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        for (opcode, count) in opcodes {
+            writeln!(
+                report,
+                "  {:<4} {:>12} {:>5.1}%",
+                opcode.mnemonic(),
+                count,
+                share(count)
+            )
+            .unwrap();
+        }
+
+        let cfg = analyze(program);
+        let mut blocks: Vec<(u64, usize)> = cfg
+            .blocks
+            .values()
+            .map(|block| {
+                let count = block
+                    .lines
+                    .iter()
+                    .map(|line| self.executions(line.address()))
+                    .sum();
+                (count, block.start)
+            })
+            .filter(|(count, _)| *count > 0)
+            .collect();
+        blocks.sort_by_key(|(count, start)| (std::cmp::Reverse(*count), *start));
+        report.push_str("\nhottest blocks:\n");
+        for (count, start) in blocks.into_iter().take(top) {
+            let block = &cfg.blocks[&start];
+            writeln!(
+                report,
+                "  {:04}..{:04} {:>12} {:>5.1}%",
+                block.start,
+                block.end,
+                count,
+                share(count)
+            )
+            .unwrap();
+            for line in &block.lines {
+                writeln!(
+                    report,
+                    "    {:>12}  {}",
+                    self.executions(line.address()),
+                    line
+                )
+                .unwrap();
+            }
+        }
+
+        report.push_str("\nhottest instructions:\n");
+        for (ip, count) in self.hotspots().into_iter().take(top) {
+            let line = match cfg.block_at(ip).and_then(|block| {
+                block
+                    .lines
+                    .iter()
+                    .find(|line| line.address() == ip)
+                    .filter(|line| matches!(line, Line::Code { .. }))
+            }) {
+                Some(line) => line.to_string(),
+                None => format!("{:04}: ? (not in the disassembly)", ip),
+            };
+            writeln!(report, "  {:>12} {:>5.1}%  {}", count, share(count), line).unwrap();
+        }
+        report
+    }
+}
+
+impl<M: Memory> Computer<M> {
+    /// Starts profiling, see [`Profile`].
+    pub fn with_profiling(mut self) -> Self {
+        self.profile = Some(Default::default());
+        self
+    }
+
+    /// The profile so far, `None` when not profiling.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::compiled::Compiled;
+    use super::super::io::{FromFn, ToFn};
+    use super::super::{Engine, RunResult};
+    use super::*;
+
+    #[test]
+    fn test_profile() {
+        // counts [11] down from 3, then reads input and halts
+        let program: Computer = "1001,11,-1,11,1005,11,0,3,11,99,0,3".parse().unwrap();
+        let mut c = program.clone().with_profiling();
+        assert_eq!(c.run(None).unwrap(), RunResult::WaitingForInput);
+        assert_eq!(c.run(Some(7)).unwrap(), RunResult::Finished);
+        let profile = c.profile().unwrap();
+        assert_eq!(profile.executions(0), 3);
+        assert_eq!(profile.executions(4), 3);
+        assert_eq!(profile.executions(7), 1);
+        assert_eq!(profile.opcode_executions(Opcode::Input), 1);
+        assert_eq!(profile.opcode_executions(Opcode::Halt), 1);
+        assert_eq!(profile.instructions(), 8);
+        assert_eq!(profile.hotspots()[..2], [(0, 3), (4, 3)]);
+        assert_eq!(profile.input_waits(), 1);
+
+        let report = profile.report(&program, 1);
+        assert!(report.starts_with("8 instructions, "));
+        assert!(report.contains("\nhottest blocks:\n  0000..0007            6  75.0%\n"));
+        assert!(report.contains("               3  0000: ADD [11], #-1 -> [11]\n"));
+        assert!(report.contains("\nhottest instructions:\n             3  37.5%  0000: ADD"));
+
+        assert!(program.profile().is_none());
+    }
+
+    #[test]
+    fn test_profile_drivers() {
+        let program: Computer = "1001,11,-1,11,1005,11,0,3,11,99,0,3".parse().unwrap();
+
+        let mut compiled = Compiled::new(program.clone().with_profiling());
+        assert_eq!(compiled.run(None).unwrap(), RunResult::WaitingForInput);
+        assert_eq!(compiled.run(Some(7)).unwrap(), RunResult::Finished);
+        let profile = compiled.computer().profile().unwrap();
+        assert_eq!(profile.instructions(), 8);
+        assert_eq!(profile.executions(0), 3);
+        assert_eq!(profile.executions(7), 1);
+        assert_eq!(profile.opcode_executions(Opcode::Halt), 1);
+        assert_eq!(profile.input_waits(), 1);
+
+        // input at hand isn't waited for
+        let mut c = program.with_profiling();
+        let result = c.run_with_io(FromFn(|| Some(7)), ToFn(|_| ()));
+        assert_eq!(result.unwrap(), RunResult::Finished);
+        let profile = c.profile().unwrap();
+        assert_eq!(profile.instructions(), 8);
+        assert_eq!(profile.input_waits(), 0);
+    }
+}
@@ -0,0 +1,142 @@
+//! Opt-in tracking of self-modifying code.
+//!
+//! Once enabled with [`Computer::with_self_modification_tracking`], the
+//! machine remembers which cells it executed (instruction words and their
+//! parameters) and which cells were written, and reports every write into an
+//! executed cell and every execution of a written cell. Tracking isn't
+//! serialized, a restored snapshot has it disabled.
+
+use std::collections::{HashMap, HashSet};
+
+use super::memory::Memory;
+use super::Computer;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SelfModification {
+    /// A cell that was executed before got overwritten, by the instruction
+    /// at `ip` (`None` for writes through [`Computer::get_mem_mut`]).
+    WroteExecuted { ip: Option<usize>, address: usize },
+    /// The instruction at `ip` executed a cell that was written, by the
+    /// instruction at `written_by` (`None` as above), since it was loaded.
+    ExecutedWritten {
+        ip: usize,
+        address: usize,
+        written_by: Option<usize>,
+    },
+}
+
+#[derive(Clone, Debug, Default)]
+pub(super) struct Tracker {
+    executed: HashSet<usize>,
+    /// Cells written and not executed since, with the writer.
+    written: HashMap<usize, Option<usize>>,
+    events: Vec<SelfModification>,
+}
+
+impl Tracker {
+    pub(super) fn execute(&mut self, ip: usize, cells: usize) {
+        for address in ip..ip + cells {
+            if let Some(written_by) = self.written.remove(&address) {
+                self.events.push(SelfModification::ExecutedWritten {
+                    ip,
+                    address,
+                    written_by,
+                });
+            }
+            self.executed.insert(address);
+        }
+    }
+
+    pub(super) fn write(&mut self, ip: Option<usize>, address: usize) {
+        if self.executed.contains(&address) {
+            self.events
+                .push(SelfModification::WroteExecuted { ip, address });
+        }
+        self.written.insert(address, ip);
+    }
+}
+
+impl<M: Memory> Computer<M> {
+    /// Starts tracking self-modifying code, see [`SelfModification`].
+    pub fn with_self_modification_tracking(mut self) -> Self {
+        self.self_modification = Some(Default::default());
+        self
+    }
+
+    /// Everything reported since tracking started (or since the last
+    /// [`Computer::take_self_modifications`]), empty when not tracking.
+    pub fn self_modifications(&self) -> &[SelfModification] {
+        self.self_modification
+            .as_ref()
+            .map_or(&[], |tracker| &tracker.events)
+    }
+
+    pub fn take_self_modifications(&mut self) -> Vec<SelfModification> {
+        self.self_modification
+            .as_mut()
+            .map(|tracker| std::mem::take(&mut tracker.events))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::RunResult;
+    use super::*;
+
+    #[test]
+    fn test_tracking() {
+        // multiplies the cell behind it into a HLT and runs it
+        let mut c: Computer = "1002,4,3,4,33".parse().unwrap();
+        c = c.with_self_modification_tracking();
+        assert_eq!(c.run(None).unwrap(), RunResult::Finished);
+        assert_eq!(
+            c.self_modifications(),
+            &[SelfModification::ExecutedWritten {
+                ip: 4,
+                address: 4,
+                written_by: Some(0)
+            }]
+        );
+
+        // overwrites its own instruction word, then gets poked from outside
+        let mut c: Computer = "1101,1,1,0,99".parse().unwrap();
+        c = c.with_self_modification_tracking();
+        c.run(None).unwrap();
+        assert_eq!(
+            c.take_self_modifications(),
+            vec![SelfModification::WroteExecuted {
+                ip: Some(0),
+                address: 0
+            }]
+        );
+        c.set_mem(4, 99).unwrap();
+        assert_eq!(
+            c.self_modifications(),
+            &[SelfModification::WroteExecuted {
+                ip: None,
+                address: 4
+            }]
+        );
+
+        let mut c: Computer = "1002,4,3,4,33".parse().unwrap();
+        c.run(None).unwrap();
+        assert!(c.self_modifications().is_empty());
+
+        // patching an input instruction that is still waiting for input
+        let mut c: Computer = "3,5,4,6,99,0,0".parse().unwrap();
+        c = c.with_self_modification_tracking();
+        assert_eq!(c.run(None).unwrap(), RunResult::WaitingForInput);
+        c.set_mem(1, 6).unwrap();
+        assert!(c.self_modifications().is_empty());
+        assert_eq!(c.run(Some(7)).unwrap(), RunResult::Output(7));
+        assert_eq!(
+            c.self_modifications(),
+            &[SelfModification::ExecutedWritten {
+                ip: 0,
+                address: 1,
+                written_by: None
+            }]
+        );
+    }
+}
@@ -0,0 +1,140 @@
+//! Checkpoints of a running [`Computer`] together with its pending I/O, stored
+//! as JSON or in a compact binary form so a session can be resumed later.
+
+use std::collections::VecDeque;
+use std::path::Path;
+
+use aoc_helpers::anyhow;
+use serde::{Deserialize, Serialize};
+
+use super::io::RunError;
+use super::{Computer, IntcodeError, RunResult};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub computer: Computer,
+    /// Input queued but not consumed by the program yet.
+    pub pending_input: VecDeque<isize>,
+    /// Output produced but not processed by the host yet.
+    pub pending_output: VecDeque<isize>,
+}
+
+impl From<Computer> for Snapshot {
+    fn from(computer: Computer) -> Self {
+        Self {
+            computer,
+            pending_input: Default::default(),
+            pending_output: Default::default(),
+        }
+    }
+}
+
+impl Snapshot {
+    /// Runs until the program halts or needs more input than is queued,
+    /// feeding it `pending_input` and collecting into `pending_output`.
+    pub fn run(&mut self) -> Result<RunResult, IntcodeError> {
+        self.computer
+            .run_with_io(&mut self.pending_input, &mut self.pending_output)
+            .map_err(RunError::into_fault)
+    }
+
+    pub fn to_json(&self) -> Result<String, anyhow::Error> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, anyhow::Error> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, anyhow::Error> {
+        Ok(bincode::serialize(self)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, anyhow::Error> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+
+    /// Saves as JSON when the path ends with `.json`, in binary form otherwise.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), anyhow::Error> {
+        let path = path.as_ref();
+        let bytes = if is_json(path) {
+            self.to_json()?.into_bytes()
+        } else {
+            self.to_bytes()?
+        };
+        std::fs::write(path, bytes)
+            .map_err(|err| anyhow::anyhow!("Writing {:?} failed: {}", path, err))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, anyhow::Error> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)
+            .map_err(|err| anyhow::anyhow!("Reading {:?} failed: {}", path, err))?;
+        if is_json(path) {
+            Self::from_json(std::str::from_utf8(&bytes)?)
+        } else {
+            Self::from_bytes(&bytes)
+        }
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // outputs the running sum of its inputs
+    const PROGRAM: &str = "3,12,1,12,13,13,4,13,1105,1,0,99,0,0";
+
+    fn session() -> Snapshot {
+        let mut snapshot: Snapshot = PROGRAM.parse::<Computer>().unwrap().into();
+        snapshot.pending_input.extend([1, 2, 3]);
+        assert_eq!(snapshot.run().unwrap(), RunResult::WaitingForInput);
+        snapshot
+    }
+
+    fn resume(mut snapshot: Snapshot) -> Vec<isize> {
+        snapshot.pending_input.extend([4, 5]);
+        assert_eq!(snapshot.run().unwrap(), RunResult::WaitingForInput);
+        snapshot.pending_output.into_iter().collect()
+    }
+
+    #[test]
+    fn test_run() {
+        assert_eq!(resume(session()), vec![1, 3, 6, 10, 15]);
+    }
+
+    #[test]
+    fn test_json() {
+        let json = session().to_json().unwrap();
+        assert_eq!(
+            resume(Snapshot::from_json(&json).unwrap()),
+            vec![1, 3, 6, 10, 15]
+        );
+    }
+
+    #[test]
+    fn test_bytes_and_files() {
+        let bytes = session().to_bytes().unwrap();
+        assert_eq!(
+            resume(Snapshot::from_bytes(&bytes).unwrap()),
+            vec![1, 3, 6, 10, 15]
+        );
+
+        // unique per process, so that concurrent runs don't share files
+        let dir = std::env::temp_dir();
+        for extension in ["json", "bin"] {
+            let name = format!("intcode-snapshot-test-{}.{}", std::process::id(), extension);
+            let path = dir.join(name);
+            let saved = session().save(&path);
+            let restored = Snapshot::load(&path);
+            let _ = std::fs::remove_file(&path);
+            saved.unwrap();
+            assert_eq!(resume(restored.unwrap()), vec![1, 3, 6, 10, 15]);
+        }
+    }
+}
@@ -0,0 +1,557 @@
+//! Execution traces: a record per executed instruction, collected in memory
+//! or streamed to a compact binary file, and tools to replay and diff them.
+//!
+//! The binary format starts with [`MAGIC`], followed by records made of a
+//! flags byte, the instruction pointer and the raw instruction word, the
+//! operand values (as many as the opcode takes) and the memory write, if
+//! any. Numbers are LEB128 varints, signed ones zigzag encoded first.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use super::memory::Memory;
+use super::{
+    Computer, DecodeError, Instruction, IntcodeError, MemoryWrite, RunResult, StepEvent, StepResult,
+};
+
+pub const MAGIC: &[u8; 5] = b"ICTR\x01";
+
+const FLAG_WRITE: u8 = 1;
+const FLAG_OUTPUT: u8 = 2;
+const FLAG_JUMP_TAKEN: u8 = 4;
+const FLAG_FINISHED: u8 = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceRecord {
+    pub ip: usize,
+    /// Raw instruction word.
+    pub instruction: isize,
+    /// Operand values, only the first `arity()` are meaningful.
+    pub operands: [isize; 3],
+    pub write: Option<MemoryWrite>,
+    pub output: bool,
+    pub jump_taken: bool,
+    pub finished: bool,
+}
+
+impl TraceRecord {
+    /// The instruction word decoded, records built by hand or read from a
+    /// damaged file may not hold a valid one.
+    pub fn decoded(&self) -> Result<Instruction, DecodeError> {
+        Instruction::try_from(self.instruction)
+    }
+
+    pub fn arity(&self) -> Result<usize, DecodeError> {
+        Ok(self.decoded()?.opcode().arity())
+    }
+
+    /// Instruction pointer after the instruction executed.
+    pub fn next_ip(&self) -> Result<usize, DecodeError> {
+        Ok(if self.finished {
+            self.ip
+        } else if self.jump_taken {
+            self.operands[1] as usize
+        } else {
+            self.ip + 1 + self.arity()?
+        })
+    }
+
+    /// Record of an executed step, `None` when the machine was waiting for input.
+    pub fn from_event(event: &StepEvent) -> Option<Self> {
+        if event.result == StepResult::WaitingForInput {
+            return None;
+        }
+        let mut operands = [0; 3];
+        for (value, operand) in operands.iter_mut().zip(event.operands.iter().flatten()) {
+            *value = operand.value;
+        }
+        Some(Self {
+            ip: event.ip,
+            instruction: isize::from(event.instruction),
+            operands,
+            write: event.write,
+            output: matches!(event.result, StepResult::Output(_)),
+            jump_taken: event.jump.is_some_and(|jump| jump.taken),
+            finished: event.result == StepResult::Finished,
+        })
+    }
+}
+
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.decoded() {
+            Ok(instruction) => write!(
+                f,
+                "{:04}: {} {:?}",
+                self.ip,
+                instruction.opcode().mnemonic(),
+                &self.operands[..instruction.opcode().arity()]
+            )?,
+            Err(err) => write!(f, "{:04}: {} ({})", self.ip, self.instruction, err)?,
+        }
+        if let Some(write) = self.write {
+            write!(f, " [{}] {} -> {}", write.address, write.old, write.new)?;
+        }
+        if self.output {
+            write!(f, " output {}", self.operands[0])?;
+        }
+        if self.jump_taken {
+            write!(f, " jump {}", self.operands[1])?;
+        }
+        if self.finished {
+            write!(f, " finished")?;
+        }
+        Ok(())
+    }
+}
+
+pub trait TraceSink {
+    fn record(&mut self, record: TraceRecord);
+}
+
+impl TraceSink for Vec<TraceRecord> {
+    fn record(&mut self, record: TraceRecord) {
+        self.push(record);
+    }
+}
+
+/// Keeps only the most recent records.
+#[derive(Clone, Debug)]
+pub struct TraceRing {
+    capacity: usize,
+    records: VecDeque<TraceRecord>,
+}
+
+impl TraceRing {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            records: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn records(&self) -> impl Iterator<Item = &TraceRecord> {
+        self.records.iter()
+    }
+}
+
+impl TraceSink for TraceRing {
+    fn record(&mut self, record: TraceRecord) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+}
+
+fn zigzag(value: isize) -> u64 {
+    let value = value as i64;
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> isize {
+    ((value >> 1) as i64 ^ -((value & 1) as i64)) as isize
+}
+
+fn write_varint<W: Write>(writer: &mut W, mut value: u64) -> io::Result<()> {
+    let mut buf = [0; 10];
+    let mut len = 0;
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf[len] = byte;
+            len += 1;
+            break;
+        }
+        buf[len] = byte | 0x80;
+        len += 1;
+    }
+    writer.write_all(&buf[..len])
+}
+
+fn read_varint<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0];
+        reader.read_exact(&mut byte)?;
+        value |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "varint too long",
+    ))
+}
+
+/// Streams records to a writer in the binary trace format.
+///
+/// Write errors are kept and reported by [`TraceWriter::finish`].
+pub struct TraceWriter<W: Write> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writer.write_all(MAGIC)?;
+        Ok(Self {
+            writer,
+            error: None,
+        })
+    }
+
+    fn write(&mut self, record: &TraceRecord) -> io::Result<()> {
+        let arity = record
+            .arity()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let mut flags = 0;
+        if record.write.is_some() {
+            flags |= FLAG_WRITE;
+        }
+        if record.output {
+            flags |= FLAG_OUTPUT;
+        }
+        if record.jump_taken {
+            flags |= FLAG_JUMP_TAKEN;
+        }
+        if record.finished {
+            flags |= FLAG_FINISHED;
+        }
+        self.writer.write_all(&[flags])?;
+        write_varint(&mut self.writer, record.ip as u64)?;
+        write_varint(&mut self.writer, zigzag(record.instruction))?;
+        for operand in &record.operands[..arity] {
+            write_varint(&mut self.writer, zigzag(*operand))?;
+        }
+        if let Some(write) = record.write {
+            write_varint(&mut self.writer, write.address as u64)?;
+            write_varint(&mut self.writer, zigzag(write.old))?;
+            write_varint(&mut self.writer, zigzag(write.new))?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> TraceSink for TraceWriter<W> {
+    fn record(&mut self, record: TraceRecord) {
+        if self.error.is_none() {
+            if let Err(err) = self.write(&record) {
+                self.error = Some(err);
+            }
+        }
+    }
+}
+
+/// Reads records written by [`TraceWriter`], stopping after the first error
+/// since the records behind it can't be told apart.
+pub struct TraceReader<R: Read> {
+    reader: R,
+    failed: bool,
+}
+
+impl<R: Read> TraceReader<R> {
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0; MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not an intcode trace",
+            ));
+        }
+        Ok(Self {
+            reader,
+            failed: false,
+        })
+    }
+
+    fn read(&mut self, flags: u8) -> io::Result<TraceRecord> {
+        let ip = read_varint(&mut self.reader)? as usize;
+        let instruction = unzigzag(read_varint(&mut self.reader)?);
+        let arity = Instruction::try_from(instruction)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?
+            .opcode()
+            .arity();
+        let mut operands = [0; 3];
+        for operand in operands.iter_mut().take(arity) {
+            *operand = unzigzag(read_varint(&mut self.reader)?);
+        }
+        let write = if flags & FLAG_WRITE != 0 {
+            Some(MemoryWrite {
+                address: read_varint(&mut self.reader)? as usize,
+                old: unzigzag(read_varint(&mut self.reader)?),
+                new: unzigzag(read_varint(&mut self.reader)?),
+            })
+        } else {
+            None
+        };
+        Ok(TraceRecord {
+            ip,
+            instruction,
+            operands,
+            write,
+            output: flags & FLAG_OUTPUT != 0,
+            jump_taken: flags & FLAG_JUMP_TAKEN != 0,
+            finished: flags & FLAG_FINISHED != 0,
+        })
+    }
+}
+
+impl<R: Read> Iterator for TraceReader<R> {
+    type Item = io::Result<TraceRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let mut flags = [0];
+        let record = match self.reader.read(&mut flags) {
+            Ok(0) => return None,
+            Ok(_) => self.read(flags[0]),
+            Err(err) => Err(err),
+        };
+        self.failed = record.is_err();
+        Some(record)
+    }
+}
+
+/// First position where two traces differ, `None` on the side that ended early.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    pub index: usize,
+    pub left: Option<TraceRecord>,
+    pub right: Option<TraceRecord>,
+}
+
+pub fn diff<A, B>(left: A, right: B) -> Option<Divergence>
+where
+    A: IntoIterator<Item = TraceRecord>,
+    B: IntoIterator<Item = TraceRecord>,
+{
+    let (mut left, mut right) = (left.into_iter(), right.into_iter());
+    let mut index = 0;
+    loop {
+        match (left.next(), right.next()) {
+            (None, None) => return None,
+            (left, right) if left != right => return Some(Divergence { index, left, right }),
+            _ => index += 1,
+        }
+    }
+}
+
+/// Applies the effects of `records` to `computer`, reconstructing the state the
+/// traced machine had after executing them. Stops at the first record
+/// with an invalid instruction, a write past the memory limit or an
+/// overflowing relative base.
+pub fn replay<M: Memory<Word = isize>, I: IntoIterator<Item = TraceRecord>>(
+    computer: &mut Computer<M>,
+    records: I,
+) -> Result<(), IntcodeError> {
+    for record in records {
+        let decode_error = |err| IntcodeError::from_decode(record.ip, record.instruction, err);
+        let instruction = record.decoded().map_err(decode_error)?;
+        if let Some(write) = record.write {
+            computer.set_mem(write.address, write.new)?;
+        }
+        if instruction.opcode() == super::Opcode::AdjustRelativeBase {
+            computer.relative_base = computer
+                .relative_base
+                .checked_add(record.operands[0])
+                .ok_or(IntcodeError::ArithmeticOverflow {
+                    ip: record.ip,
+                    instruction: record.instruction,
+                })?;
+        }
+        computer.idx = record.next_ip().map_err(decode_error)?;
+    }
+    Ok(())
+}
+
+impl<M: Memory<Word = isize>> Computer<M> {
+    /// Like [`Computer::run`], reporting every executed instruction to `sink`.
+    pub fn run_traced<S: TraceSink>(
+        &mut self,
+        mut input: Option<isize>,
+        sink: &mut S,
+    ) -> Result<RunResult, IntcodeError> {
+        loop {
+            let event = self.step(&mut input)?;
+            if let Some(record) = TraceRecord::from_event(&event) {
+                sink.record(record);
+            }
+            match event.result {
+                StepResult::Executed => {}
+                StepResult::WaitingForInput => return Ok(RunResult::WaitingForInput),
+                StepResult::Output(output) => return Ok(RunResult::Output(output)),
+                StepResult::Finished => return Ok(RunResult::Finished),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM: &str = "3,9,8,9,10,9,4,9,99,-1,8";
+
+    fn trace(input: isize) -> (Computer, Vec<TraceRecord>) {
+        let mut computer: Computer = PROGRAM.parse().unwrap();
+        let mut records = Vec::new();
+        let mut input = Some(input);
+        while computer.run_traced(input.take(), &mut records).unwrap() != RunResult::Finished {}
+        (computer, records)
+    }
+
+    #[test]
+    fn test_records() {
+        let (_, records) = trace(8);
+        let lines: Vec<String> = records.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "0000: IN [-1] [9] -1 -> 8",
+                "0002: EQ [8, 8, 8] [9] 8 -> 1",
+                "0006: OUT [1] output 1",
+                "0008: HLT [] finished",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let (_, records) = trace(8);
+        let mut writer = TraceWriter::new(Vec::new()).unwrap();
+        for record in &records {
+            writer.record(*record);
+        }
+        let bytes = writer.finish().unwrap();
+        let read: Vec<TraceRecord> = TraceReader::new(bytes.as_slice())
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(read, records);
+        assert!(TraceReader::new(&b"nope!"[..]).is_err());
+    }
+
+    #[test]
+    fn test_ring() {
+        let (_, records) = trace(8);
+        let mut ring = TraceRing::new(2);
+        records.iter().for_each(|record| ring.record(*record));
+        assert_eq!(
+            ring.records().copied().collect::<Vec<_>>(),
+            records[2..].to_vec()
+        );
+    }
+
+    #[test]
+    fn test_diff_and_replay() {
+        let (finished, equal) = trace(8);
+        let (_, not_equal) = trace(7);
+        assert_eq!(diff(equal.clone(), equal.clone()), None);
+        let divergence = diff(equal.clone(), not_equal.clone()).unwrap();
+        assert_eq!(divergence.index, 0);
+        assert_eq!(divergence.left, Some(equal[0]));
+        assert_eq!(
+            diff(equal.clone(), equal[..2].to_vec()).unwrap().right,
+            None
+        );
+
+        let mut replayed: Computer = PROGRAM.parse().unwrap();
+        replay(&mut replayed, equal.clone()).unwrap();
+        assert_eq!(replayed.mem, finished.mem);
+        assert_eq!(replayed.ip(), finished.ip());
+
+        let mut invalid = equal[1];
+        invalid.instruction = 42;
+        let mut replayed: Computer = PROGRAM.parse().unwrap();
+        assert_eq!(
+            replay(&mut replayed, [equal[0], invalid, equal[2]]),
+            Err(IntcodeError::UnknownOpcode {
+                ip: 2,
+                instruction: 42
+            })
+        );
+        assert_eq!(replayed.ip(), 2);
+
+        // damaged records
+        let mut far = equal[1];
+        far.write = Some(MemoryWrite {
+            address: 1 << 40,
+            old: 0,
+            new: 1,
+        });
+        let mut replayed: Computer = PROGRAM.parse().unwrap();
+        assert!(matches!(
+            replay(&mut replayed, [far]),
+            Err(IntcodeError::MemoryLimit { address, .. }) if address == 1 << 40
+        ));
+        let overflow = TraceRecord {
+            ip: 0,
+            instruction: 109,
+            operands: [1, 0, 0],
+            write: None,
+            output: false,
+            jump_taken: false,
+            finished: false,
+        };
+        let mut replayed: Computer = PROGRAM.parse().unwrap();
+        assert_eq!(replay(&mut replayed, [overflow, overflow]), Ok(()));
+        assert_eq!(replayed.relative_base(), 2);
+        let mut overflow = overflow;
+        overflow.operands[0] = isize::MAX;
+        assert_eq!(
+            replay(&mut replayed, [overflow]),
+            Err(IntcodeError::ArithmeticOverflow {
+                ip: 0,
+                instruction: 109
+            })
+        );
+        assert_eq!(replayed.relative_base(), 2);
+    }
+
+    #[test]
+    fn test_invalid_records() {
+        let (_, records) = trace(8);
+        let mut invalid = records[1];
+        invalid.instruction = 42;
+        assert_eq!(invalid.decoded(), Err(DecodeError::UnknownOpcode(42)));
+        assert_eq!(invalid.next_ip(), Err(DecodeError::UnknownOpcode(42)));
+        assert_eq!(
+            invalid.to_string(),
+            "0002: 42 (Unknown opcode: 42) [9] 8 -> 1"
+        );
+
+        let mut writer = TraceWriter::new(Vec::new()).unwrap();
+        writer.record(invalid);
+        assert!(writer.finish().is_err());
+
+        // a record with an unknown opcode followed by valid ones
+        let mut writer = TraceWriter::new(Vec::new()).unwrap();
+        for record in &records {
+            writer.record(*record);
+        }
+        let mut bytes = writer.finish().unwrap();
+        bytes[MAGIC.len() + 2] = 42;
+        let read: Vec<io::Result<TraceRecord>> =
+            TraceReader::new(bytes.as_slice()).unwrap().collect();
+        assert_eq!(read.len(), 1);
+        assert!(read[0].is_err());
+    }
+}
@@ -0,0 +1,491 @@
+//! Transpiler turning a program into a standalone Rust source file.
+//!
+//! Every block of the [control-flow graph](super::analysis) becomes an arm
+//! of a `match` on the instruction pointer, with each instruction translated
+//! into a line or two of Rust under its disassembly. All jumps, computed or
+//! not, go through that `match`, which serves as the dispatch table.
+//!
+//! Addresses that don't start a block, and blocks the program is known to
+//! write into, are left to an interpreter embedded in the generated file.
+//! The first write into transpiled code at runtime hands the rest of the run
+//! over to that interpreter as well.
+//!
+//! The generated program reads its input as integers from stdin and prints
+//! one output per line. Faults panic. [`build`] and [`run_built`] build and
+//! run it from Rust, and `transpile --compare` times it against the
+//! interpreter.
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use aoc_helpers::anyhow;
+
+use super::analysis::{analyze, Block};
+use super::disasm::Line;
+use super::memory::Memory;
+use super::{Computer, Mode, Opcode};
+
+/// Everything but the program and its blocks.
+const RUNTIME: &str = r#"struct Vm {
+    mem: Vec<i64>,
+    ip: usize,
+    rb: i64,
+    /// Set once the program writes into transpiled code.
+    interpret: bool,
+    input: Vec<i64>,
+    output: BufWriter<Stdout>,
+}
+
+fn add(a: i64, b: i64) -> i64 {
+    a.checked_add(b).expect("arithmetic overflow")
+}
+
+fn mul(a: i64, b: i64) -> i64 {
+    a.checked_mul(b).expect("arithmetic overflow")
+}
+
+impl Vm {
+    fn new() -> Self {
+        Self {
+            mem: PROGRAM.to_vec(),
+            ip: 0,
+            rb: 0,
+            interpret: false,
+            input: Vec::new(),
+            output: BufWriter::new(std::io::stdout()),
+        }
+    }
+
+    fn addr(&self, address: i64) -> usize {
+        usize::try_from(address)
+            .unwrap_or_else(|_| panic!("negative address {} near {}", address, self.ip))
+    }
+
+    fn rd(&self, address: i64) -> i64 {
+        self.mem.get(self.addr(address)).copied().unwrap_or(0)
+    }
+
+    /// Returns whether the write landed in transpiled code.
+    fn wr(&mut self, address: i64, value: i64) -> bool {
+        let address = self.addr(address);
+        if address >= self.mem.len() {
+            self.mem.resize(address + 1, 0);
+        }
+        self.mem[address] = value;
+        if is_code(address) {
+            self.interpret = true;
+        }
+        self.interpret
+    }
+
+    fn read(&mut self) -> i64 {
+        self.output.flush().unwrap();
+        while self.input.is_empty() {
+            let mut line = String::new();
+            if std::io::stdin().lock().read_line(&mut line).unwrap() == 0 {
+                panic!("input exhausted at {}", self.ip);
+            }
+            self.input = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|word| !word.is_empty())
+                .rev()
+                .map(|word| word.parse().expect("input must be integers"))
+                .collect();
+        }
+        self.input.pop().unwrap()
+    }
+
+    fn write(&mut self, value: i64) {
+        writeln!(self.output, "{}", value).unwrap();
+    }
+
+    /// Address of parameter `n` of the instruction at `ip`.
+    fn param(&self, word: i64, n: u32) -> i64 {
+        let raw = (self.ip + n as usize) as i64;
+        match word / 10i64.pow(n + 1) % 10 {
+            0 => self.rd(raw),
+            1 => raw,
+            2 => add(self.rb, self.rd(raw)),
+            mode => panic!("unknown mode {} at {}", mode, self.ip),
+        }
+    }
+
+    /// Runs the instruction at `ip` the slow way, returns false on halt.
+    fn step(&mut self) -> bool {
+        let word = self.rd(self.ip as i64);
+        let arg = |vm: &Self, n: u32| vm.rd(vm.param(word, n));
+        let target = |vm: &Self, n: u32| {
+            if word / 10i64.pow(n + 1) % 10 == 1 {
+                panic!("write to an immediate mode parameter at {}", vm.ip);
+            }
+            vm.param(word, n)
+        };
+        match word % 100 {
+            1 | 2 | 7 | 8 => {
+                let (a, b) = (arg(self, 1), arg(self, 2));
+                let value = match word % 100 {
+                    1 => add(a, b),
+                    2 => mul(a, b),
+                    7 => (a < b) as i64,
+                    _ => (a == b) as i64,
+                };
+                let address = target(self, 3);
+                self.wr(address, value);
+                self.ip += 4;
+            }
+            3 => {
+                let address = target(self, 1);
+                let value = self.read();
+                self.wr(address, value);
+                self.ip += 2;
+            }
+            4 => {
+                let value = arg(self, 1);
+                self.write(value);
+                self.ip += 2;
+            }
+            5 | 6 => {
+                if (arg(self, 1) != 0) == (word % 100 == 5) {
+                    self.ip = self.addr(arg(self, 2));
+                } else {
+                    self.ip += 3;
+                }
+            }
+            9 => {
+                self.rb = add(self.rb, arg(self, 1));
+                self.ip += 2;
+            }
+            99 => return false,
+            opcode => panic!("unknown opcode {} at {}", opcode, self.ip),
+        }
+        true
+    }
+}
+"#;
+
+fn value(mode: Mode, param: isize) -> String {
+    match mode {
+        Mode::Position => format!("m.rd({})", param),
+        Mode::Immediate if param < 0 => format!("({})", param),
+        Mode::Immediate => param.to_string(),
+        Mode::Relative => format!("m.rd({})", relative(param)),
+    }
+}
+
+/// `None` for immediate parameters, which can't be written to.
+fn address(mode: Mode, param: isize) -> Option<String> {
+    match mode {
+        Mode::Position => Some(param.to_string()),
+        Mode::Immediate => None,
+        Mode::Relative => Some(relative(param)),
+    }
+}
+
+fn relative(offset: isize) -> String {
+    format!("add(m.rb, {})", offset)
+}
+
+/// Rust for a single instruction inside a block arm.
+fn statement(line: &Line, code: &mut String) {
+    let Line::Code {
+        address: ip,
+        instruction,
+        params,
+    } = *line
+    else {
+        return;
+    };
+    let next = ip + line.size();
+    let [a, b, c] = instruction.modes();
+    // the same fault as the embedded interpreter's
+    let fault = format!(
+        "                panic!(\"write to an immediate mode parameter at {}\");",
+        ip
+    );
+    let store = |target: Option<String>, code: &mut String| match target {
+        Some(target) => {
+            writeln!(code, "                if m.wr({}, v) {{", target).unwrap();
+            writeln!(code, "                    m.ip = {};", next).unwrap();
+            writeln!(code, "                    continue;\n                }}").unwrap();
+        }
+        None => writeln!(code, "                let _ = v;\n{}", fault).unwrap(),
+    };
+    writeln!(code, "                // {}", line).unwrap();
+    match instruction.opcode() {
+        Opcode::Add | Opcode::Mul | Opcode::LessThan | Opcode::Equals => {
+            let (x, y) = (value(a, params[0]), value(b, params[1]));
+            let expression = match instruction.opcode() {
+                Opcode::Add => format!("add({}, {})", x, y),
+                Opcode::Mul => format!("mul({}, {})", x, y),
+                Opcode::LessThan => format!("({} < {}) as i64", x, y),
+                _ => format!("({} == {}) as i64", x, y),
+            };
+            writeln!(code, "                let v = {};", expression).unwrap();
+            store(address(c, params[2]), code);
+        }
+        // the target is checked before reading
+        Opcode::Input => match address(a, params[0]) {
+            Some(target) => {
+                writeln!(
+                    code,
+                    "                m.ip = {};\n                let v = m.read();",
+                    ip
+                )
+                .unwrap();
+                store(Some(target), code);
+            }
+            None => writeln!(code, "{}", fault).unwrap(),
+        },
+        Opcode::Output => {
+            writeln!(code, "                m.write({});", value(a, params[0])).unwrap();
+        }
+        Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+            let comparison = if instruction.opcode() == Opcode::JumpIfTrue {
+                "!="
+            } else {
+                "=="
+            };
+            let target = match usize::try_from(params[1]) {
+                Ok(target) if b == Mode::Immediate => target.to_string(),
+                _ => format!("m.addr({})", value(b, params[1])),
+            };
+            writeln!(
+                code,
+                "                if {} {} 0 {{\n                    m.ip = {};\n                    continue;\n                }}",
+                value(a, params[0]),
+                comparison,
+                target
+            )
+            .unwrap();
+        }
+        Opcode::AdjustRelativeBase => {
+            writeln!(
+                code,
+                "                m.rb = add(m.rb, {});",
+                value(a, params[0])
+            )
+            .unwrap();
+        }
+        Opcode::Halt => writeln!(code, "                break;").unwrap(),
+    }
+}
+
+fn block_arm(block: &Block, code: &mut String) {
+    writeln!(code, "            {} => {{", block.start).unwrap();
+    for line in &block.lines {
+        statement(line, code);
+    }
+    let halts = matches!(
+        block.lines.last(),
+        Some(Line::Code { instruction, .. }) if instruction.opcode() == Opcode::Halt
+    );
+    if !halts {
+        writeln!(code, "                m.ip = {};", block.end).unwrap();
+    }
+    writeln!(code, "            }}").unwrap();
+}
+
+/// Generates a Rust program behaving like `computer`; build it with
+/// `rustc -O` and pass input on stdin.
+pub fn transpile<M: Memory<Word = isize>>(computer: &Computer<M>) -> String {
+    let cfg = analyze(computer);
+    let written: BTreeSet<usize> = cfg
+        .code_writes
+        .iter()
+        .filter_map(|write| cfg.block_at(write.target))
+        .map(|block| block.start)
+        .collect();
+    let blocks: Vec<&Block> = cfg
+        .blocks
+        .values()
+        .filter(|block| !written.contains(&block.start))
+        .collect();
+
+    let mut code = String::from(
+        "// Generated by the intcode transpiler, blocks of the original program are\n\
+         // listed above their translation.\n\
+         #![allow(unused_parens, unreachable_code)]\n\n\
+         use std::io::{BufRead, BufWriter, Stdout, Write};\n\n",
+    );
+    code.push_str("const PROGRAM: &[i64] = &[");
+    for address in 0..computer.mem_len() {
+        if address % 16 == 0 {
+            code.push_str("\n   ");
+        }
+        write!(code, " {},", computer.get_mem(address)).unwrap();
+    }
+    code.push_str("\n];\n\n");
+
+    code.push_str(
+        "/// Whether `address` belongs to transpiled code.\nfn is_code(address: usize) -> bool {\n",
+    );
+    let ranges: Vec<String> = blocks
+        .iter()
+        .map(|block| format!("{}..={}", block.start, block.end - 1))
+        .collect();
+    if ranges.is_empty() {
+        code.push_str("    let _ = address;\n    false\n}\n\n");
+    } else {
+        writeln!(
+            code,
+            "    matches!(\n        address,\n        {}\n    )\n}}\n",
+            ranges.join("\n            | ")
+        )
+        .unwrap();
+    }
+    code.push_str(RUNTIME);
+
+    code.push_str("\nfn main() {\n    let mut m = Vm::new();\n    loop {\n");
+    code.push_str("        if m.interpret {\n            if !m.step() {\n                break;\n            }\n            continue;\n        }\n");
+    code.push_str("        match m.ip {\n");
+    for block in blocks {
+        block_arm(block, &mut code);
+    }
+    code.push_str("            _ => {\n                if !m.step() {\n                    break;\n                }\n            }\n");
+    code.push_str("        }\n    }\n    m.output.flush().unwrap();\n}\n");
+    code
+}
+
+/// Transpiles `computer` into `dir/name.rs` and builds it with `rustc -O`
+/// (or `$RUSTC`), returning the path of the executable.
+pub fn build<M: Memory<Word = isize>>(
+    computer: &Computer<M>,
+    dir: &Path,
+    name: &str,
+) -> Result<PathBuf, anyhow::Error> {
+    let source = dir.join(format!("{}.rs", name));
+    let executable = dir.join(name);
+    std::fs::write(&source, transpile(computer))?;
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_owned());
+    let output = Command::new(&rustc)
+        .args(["--edition", "2021", "-O", "-o"])
+        .arg(&executable)
+        .arg(&source)
+        .output()
+        .map_err(|err| anyhow::anyhow!("Can't run {}: {}", rustc, err))?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "Building {:?} failed:\n{}",
+            source,
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    Ok(executable)
+}
+
+/// Runs a program built by [`build`] with `inputs` on stdin, returning its
+/// outputs.
+pub fn run_built(executable: &Path, inputs: &[isize]) -> Result<Vec<isize>, anyhow::Error> {
+    let stdin: Vec<String> = inputs.iter().map(|input| input.to_string()).collect();
+    let mut child = Command::new(executable)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // the program reads lazily, so a program ignoring its input may exit
+    // before it's written
+    let _ = std::io::Write::write_all(
+        child.stdin.as_mut().unwrap(),
+        (stdin.join("\n") + "\n").as_bytes(),
+    );
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "{:?} failed:\n{}",
+            executable,
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| {
+            line.parse()
+                .map_err(|err| anyhow::anyhow!("Parsing {:?} to int failed: {}", line, err))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::asm::assemble;
+    use super::super::{load, RunResult};
+    use super::*;
+
+    /// Outputs of the interpreter given `inputs`.
+    fn interpret(mut computer: Computer, inputs: &[isize]) -> Vec<isize> {
+        let mut inputs = inputs.iter().copied();
+        let mut input = None;
+        let mut outputs = Vec::new();
+        loop {
+            match computer.run(input.take()).unwrap() {
+                RunResult::Output(output) => outputs.push(output),
+                RunResult::WaitingForInput => input = Some(inputs.next().unwrap()),
+                RunResult::Finished => return outputs,
+            }
+        }
+    }
+
+    #[test]
+    fn test_transpile() {
+        let computer = assemble(
+            "
+                    in -> [x]
+                    jf [x], #end
+                    mov #104 -> [patch]
+            patch:  out #5
+            end:    hlt
+            x:      .data 0
+            ",
+        )
+        .unwrap();
+        let code = transpile(&computer);
+        assert!(code.contains("const PROGRAM: &[i64] = &[\n    3, 12, 1006, 12, 11,"));
+        // the patched block is left to the interpreter
+        assert!(code
+            .contains("matches!(\n        address,\n        0..=4\n            | 11..=11\n    )"));
+        assert!(code.contains(
+            "            0 => {\n                // 0000: IN -> [12]\n                m.ip = 0;\n                let v = m.read();\n"
+        ));
+        assert!(
+            code.contains("                if m.rd(12) == 0 {\n                    m.ip = 11;\n")
+        );
+        assert!(code.contains("            11 => {\n                // 0011: HLT\n                break;\n            }\n"));
+        assert!(!code.contains("            5 => {"));
+    }
+
+    #[test]
+    #[ignore = "builds with rustc -O, run with --ignored"]
+    fn test_transpiled_programs() {
+        let dir =
+            std::env::temp_dir().join(format!("intcode-transpile-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let result = std::panic::catch_unwind(|| {
+            for (day, inputs) in [("day05", [1]), ("day05", [5]), ("day09", [1])] {
+                let program = load(day).unwrap();
+                let executable = build(&program, &dir, day).unwrap();
+                assert_eq!(
+                    run_built(&executable, &inputs).unwrap(),
+                    interpret(program, &inputs),
+                    "{} {:?}",
+                    day,
+                    inputs
+                );
+            }
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+        result.unwrap();
+    }
+
+    #[test]
+    fn test_immediate_target() {
+        let code = transpile(&"11101,1,1,0,103,0,99".parse::<Computer>().unwrap());
+        assert!(code.contains(
+            "                let v = add(1, 1);\n                let _ = v;\n                panic!(\"write to an immediate mode parameter at 0\");\n"
+        ));
+        assert!(code.contains(
+            "                // 0004: IN -> #0\n                panic!(\"write to an immediate mode parameter at 4\");\n"
+        ));
+    }
+}
@@ -0,0 +1,83 @@
+//! Word types the machine can compute with.
+//!
+//! Fixed width words report overflowing arithmetic as
+//! [`IntcodeError::ArithmeticOverflow`](super::IntcodeError::ArithmeticOverflow),
+//! `BigInt` (behind the `bigint` feature) never overflows.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+pub trait Word:
+    Clone + Default + PartialOrd + fmt::Debug + fmt::Display + FromStr + Serialize + DeserializeOwned
+{
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+
+    fn from_isize(value: isize) -> Self;
+
+    /// The value as `isize` if it fits, instructions are decoded from it.
+    fn to_isize(&self) -> Option<isize>;
+
+    /// The value clamped to the `isize` range, for reporting words that
+    /// don't fit (addresses that don't fit fault instead).
+    fn saturating_isize(&self) -> isize {
+        self.to_isize().unwrap_or(if *self < Self::default() {
+            isize::MIN
+        } else {
+            isize::MAX
+        })
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+macro_rules! impl_fixed_width_word {
+    ($($word:ty),*) => {
+        $(
+            impl Word for $word {
+                fn checked_add(&self, other: &Self) -> Option<Self> {
+                    <$word>::checked_add(*self, *other)
+                }
+
+                fn checked_mul(&self, other: &Self) -> Option<Self> {
+                    <$word>::checked_mul(*self, *other)
+                }
+
+                fn from_isize(value: isize) -> Self {
+                    value as $word
+                }
+
+                fn to_isize(&self) -> Option<isize> {
+                    isize::try_from(*self).ok()
+                }
+            }
+        )*
+    };
+}
+
+impl_fixed_width_word!(isize, i64, i128);
+
+#[cfg(feature = "bigint")]
+impl Word for num_bigint::BigInt {
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        Some(self * other)
+    }
+
+    fn from_isize(value: isize) -> Self {
+        value.into()
+    }
+
+    fn to_isize(&self) -> Option<isize> {
+        self.try_into().ok()
+    }
+}